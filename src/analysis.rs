@@ -0,0 +1,439 @@
+use std::fmt;
+
+use crate::{
+    evaluator::{Evaluator, PartialEvaluator},
+    hint::{hint, MoveHint},
+};
+use bkgm::{dice::ALL_21, Dice, GameState::GameOver, State};
+
+/// Equity losses from which a move counts as doubtful, an error or a blunder.
+pub const DOUBTFUL: f32 = 0.04;
+pub const ERROR: f32 = 0.08;
+pub const BLUNDER: f32 = 0.16;
+/// Luck from which a roll counts as lucky or very lucky, unlucky rolls being the negatives.
+pub const LUCKY: f32 = 0.3;
+pub const VERY_LUCKY: f32 = 0.6;
+
+/// A played game: the starting position and every checker play in order, the players
+/// taking turns and the first move being made by the player on roll in `start`.
+/// Every move is the position after it, as returned by `possible_positions`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameRecord<G: State> {
+    pub start: G,
+    pub moves: Vec<(Dice, G)>,
+    /// Whether the first roll is an opening roll, which is never a double.
+    pub opening: bool,
+}
+
+impl<G: State> GameRecord<G> {
+    pub fn new(start: G) -> Self {
+        Self {
+            start,
+            moves: Vec::new(),
+            opening: false,
+        }
+    }
+
+    /// Marks the first roll as an opening roll, so that its luck is measured against the non-doubles only.
+    pub fn with_opening_roll(mut self) -> Self {
+        self.opening = true;
+        self
+    }
+
+    pub fn push(&mut self, dice: Dice, position: G) {
+        self.moves.push((dice, position));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnalysisError {
+    /// The move with this index isn't a legal move for its dice.
+    IllegalMove(usize),
+    /// The move with this index was made after the game was over.
+    GameOver(usize),
+}
+
+impl fmt::Display for AnalysisError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnalysisError::IllegalMove(index) => write!(f, "move {} is illegal", index + 1),
+            AnalysisError::GameOver(index) => {
+                write!(f, "move {} was made after the game was over", index + 1)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AnalysisError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveClass {
+    Good,
+    Doubtful,
+    Error,
+    Blunder,
+}
+
+impl MoveClass {
+    pub fn from_loss(equity_loss: f32) -> Self {
+        if equity_loss >= BLUNDER {
+            MoveClass::Blunder
+        } else if equity_loss >= ERROR {
+            MoveClass::Error
+        } else if equity_loss >= DOUBTFUL {
+            MoveClass::Doubtful
+        } else {
+            MoveClass::Good
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveAnalysis<G: State> {
+    /// 0 for the player on roll in the starting position, 1 for the opponent.
+    pub player: usize,
+    pub dice: Dice,
+    pub played: MoveHint<G>,
+    pub best: MoveHint<G>,
+    /// Number of legal moves, 1 for a forced move.
+    pub candidates: usize,
+    pub class: MoveClass,
+}
+
+impl<G: State> MoveAnalysis<G> {
+    pub fn equity_loss(&self) -> f32 {
+        self.played.equity_loss
+    }
+
+    pub fn forced(&self) -> bool {
+        self.candidates <= 1
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PlayerSummary {
+    /// Moves with more than one legal option, forced moves don't count.
+    pub unforced: usize,
+    pub total_loss: f32,
+    pub doubtful: usize,
+    pub errors: usize,
+    pub blunders: usize,
+}
+
+impl PlayerSummary {
+    fn add<G: State>(&mut self, analysis: &MoveAnalysis<G>) {
+        if analysis.forced() {
+            return;
+        }
+        self.unforced += 1;
+        self.total_loss += analysis.equity_loss();
+        match analysis.class {
+            MoveClass::Good => {}
+            MoveClass::Doubtful => self.doubtful += 1,
+            MoveClass::Error => self.errors += 1,
+            MoveClass::Blunder => self.blunders += 1,
+        }
+    }
+
+    /// Average equity lost per unforced move, in millipoints.
+    pub fn error_rate(&self) -> f32 {
+        match self.unforced {
+            0 => 0.0,
+            unforced => 1000.0 * self.total_loss / unforced as f32,
+        }
+    }
+
+    /// gnubg style performance rating, half the error rate. Lower is better, world class
+    /// players being below 5.
+    pub fn performance_rating(&self) -> f32 {
+        self.error_rate() / 2.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameAnalysis<G: State> {
+    pub moves: Vec<MoveAnalysis<G>>,
+    pub players: [PlayerSummary; 2],
+}
+
+/// Compares every move of `record` with the best move according to `evaluator`.
+/// Wrap the evaluator in a `PlyEvaluator` for a deeper analysis.
+pub fn analyse<G: State, E: Evaluator<G>>(
+    evaluator: &E,
+    record: &GameRecord<G>,
+) -> Result<GameAnalysis<G>, AnalysisError> {
+    let mut moves = Vec::with_capacity(record.moves.len());
+    let mut players = [PlayerSummary::default(); 2];
+    let mut pos = record.start;
+    for (index, (dice, played)) in record.moves.iter().enumerate() {
+        if let GameOver(_) = pos.game_state() {
+            return Err(AnalysisError::GameOver(index));
+        }
+        let hints = hint(evaluator, &pos, dice);
+        let candidates = hints.len();
+        let played = hints
+            .iter()
+            .find(|hint| hint.position == *played)
+            .ok_or(AnalysisError::IllegalMove(index))?
+            .clone();
+        let analysis = MoveAnalysis {
+            player: index % 2,
+            dice: *dice,
+            class: MoveClass::from_loss(played.equity_loss),
+            best: hints[0].clone(),
+            played,
+            candidates,
+        };
+        players[analysis.player].add(&analysis);
+        pos = analysis.played.position;
+        moves.push(analysis);
+    }
+    Ok(GameAnalysis { moves, players })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LuckClass {
+    VeryLucky,
+    Lucky,
+    Neutral,
+    Unlucky,
+    VeryUnlucky,
+}
+
+impl LuckClass {
+    pub fn from_luck(luck: f32) -> Self {
+        if luck > VERY_LUCKY {
+            LuckClass::VeryLucky
+        } else if luck > LUCKY {
+            LuckClass::Lucky
+        } else if luck < -VERY_LUCKY {
+            LuckClass::VeryUnlucky
+        } else if luck < -LUCKY {
+            LuckClass::Unlucky
+        } else {
+            LuckClass::Neutral
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RollLuck {
+    /// 0 for the player on roll in the starting position, 1 for the opponent.
+    pub player: usize,
+    pub dice: Dice,
+    pub luck: f32,
+    pub class: LuckClass,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LuckReport {
+    pub rolls: Vec<RollLuck>,
+    /// Total luck of both players.
+    pub players: [f32; 2],
+}
+
+impl LuckReport {
+    /// Luck of the player who started minus the luck of the opponent.
+    pub fn net(&self) -> f32 {
+        self.players[0] - self.players[1]
+    }
+}
+
+/// Value of `pos` for the player on roll after rolling `dice` and playing the best move.
+pub fn roll_equity<G: State, E: PartialEvaluator<G>>(evaluator: &E, pos: &G, dice: &Dice) -> f32 {
+    let positions = pos.possible_positions(dice);
+    evaluator
+        .try_eval_batch(&positions)
+        .into_iter()
+        .zip(&positions)
+        .map(|(value, position)| match position.game_state() {
+            GameOver(result) => -result.value(),
+            _ => -value,
+        })
+        .fold(f32::NEG_INFINITY, f32::max)
+}
+
+fn is_double(dice: &Dice) -> bool {
+    (1..=6).any(|die| *dice == Dice::new(die, die))
+}
+
+/// Value of the roll `dice` minus the average value of all rolls, both with best play.
+/// Luck is in the units of `evaluator`, so use one that evaluates equities.
+pub fn roll_luck<G: State, E: PartialEvaluator<G>>(evaluator: &E, pos: &G, dice: &Dice) -> f32 {
+    luck_among(evaluator, pos, dice, true)
+}
+
+/// Like `roll_luck`, but compared with the average of the 15 non-doubles, the only possible opening rolls.
+pub fn opening_roll_luck<G: State, E: PartialEvaluator<G>>(
+    evaluator: &E,
+    pos: &G,
+    dice: &Dice,
+) -> f32 {
+    luck_among(evaluator, pos, dice, false)
+}
+
+/// Value of `dice` minus the average value of the rolls of `ALL_21`, leaving out doubles unless `doubles`.
+fn luck_among<G: State, E: PartialEvaluator<G>>(
+    evaluator: &E,
+    pos: &G,
+    dice: &Dice,
+    doubles: bool,
+) -> f32 {
+    let mut total = 0.0;
+    let mut weight = 0.0;
+    let mut rolled = None;
+    for (roll, n) in ALL_21 {
+        if !doubles && is_double(&roll) {
+            continue;
+        }
+        let equity = roll_equity(evaluator, pos, &roll);
+        if roll == *dice {
+            rolled = Some(equity);
+        }
+        total += equity * n as f32;
+        weight += n as f32;
+    }
+    let rolled = rolled.unwrap_or_else(|| roll_equity(evaluator, pos, dice));
+    rolled - total / weight
+}
+
+/// Luck of every roll of `record` according to `evaluator`.
+pub fn luck<G: State, E: PartialEvaluator<G>>(
+    evaluator: &E,
+    record: &GameRecord<G>,
+) -> Result<LuckReport, AnalysisError> {
+    let mut rolls = Vec::with_capacity(record.moves.len());
+    let mut players = [0.0; 2];
+    let mut pos = record.start;
+    for (index, (dice, played)) in record.moves.iter().enumerate() {
+        if let GameOver(_) = pos.game_state() {
+            return Err(AnalysisError::GameOver(index));
+        }
+        let luck = match record.opening && index == 0 {
+            true => opening_roll_luck(evaluator, &pos, dice),
+            false => roll_luck(evaluator, &pos, dice),
+        };
+        players[index % 2] += luck;
+        rolls.push(RollLuck {
+            player: index % 2,
+            dice: *dice,
+            luck,
+            class: LuckClass::from_luck(luck),
+        });
+        pos = *played;
+    }
+    Ok(LuckReport { rolls, players })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{
+        analyse, is_double, luck, opening_roll_luck, roll_luck, AnalysisError, GameRecord,
+        LuckClass, MoveClass, PlayerSummary,
+    };
+    use crate::bearoff::TwoSidedBearoff;
+    use crate::evaluator::{PartialEvaluator, PubEval};
+    use bkgm::{dice::ALL_21, pos, Dice, GameState::GameOver, State, BACKGAMMON};
+
+    #[test]
+    fn classes_and_rates() {
+        assert_eq!(MoveClass::from_loss(0.0), MoveClass::Good);
+        assert_eq!(MoveClass::from_loss(0.05), MoveClass::Doubtful);
+        assert_eq!(MoveClass::from_loss(0.08), MoveClass::Error);
+        assert_eq!(MoveClass::from_loss(0.5), MoveClass::Blunder);
+
+        let summary = PlayerSummary {
+            unforced: 20,
+            total_loss: 0.2,
+            doubtful: 0,
+            errors: 0,
+            blunders: 1,
+        };
+        assert!((summary.error_rate() - 10.0).abs() < 1e-4);
+        assert!((summary.performance_rating() - 5.0).abs() < 1e-4);
+        assert_eq!(PlayerSummary::default().error_rate(), 0.0);
+    }
+
+    #[test]
+    fn luck_classes() {
+        assert_eq!(LuckClass::from_luck(0.7), LuckClass::VeryLucky);
+        assert_eq!(LuckClass::from_luck(0.4), LuckClass::Lucky);
+        assert_eq!(LuckClass::from_luck(0.3), LuckClass::Neutral);
+        assert_eq!(LuckClass::from_luck(-0.4), LuckClass::Unlucky);
+        assert_eq!(LuckClass::from_luck(-0.7), LuckClass::VeryUnlucky);
+    }
+
+    #[test]
+    fn analyse_bearoff() {
+        let bearoff = TwoSidedBearoff::generate(2);
+        // the opponent bears off next turn, so only bearing off both checkers wins
+        let start = pos!(x 2:1, 1:1; o 24:1);
+        let dice = Dice::new(2, 1);
+        let positions = start.possible_positions(&dice);
+        let win = *positions
+            .iter()
+            .find(|pos| matches!(pos.game_state(), GameOver(_)))
+            .unwrap();
+        let blunder = *positions.iter().find(|pos| **pos != win).unwrap();
+
+        let mut record = GameRecord::new(start);
+        record.push(dice, blunder);
+        let analysis = analyse(&bearoff, &record).unwrap();
+        assert_eq!(analysis.moves[0].class, MoveClass::Blunder);
+        assert_eq!(analysis.moves[0].best.position, win);
+        assert!((analysis.moves[0].equity_loss() - 2.0).abs() < 1e-3);
+        assert_eq!(analysis.players[0].blunders, 1);
+
+        let mut record = GameRecord::new(start);
+        record.push(dice, win);
+        assert_eq!(analyse(&bearoff, &record).unwrap().players[0].blunders, 0);
+        record.push(Dice::new(3, 1), BACKGAMMON);
+        assert_eq!(analyse(&bearoff, &record), Err(AnalysisError::GameOver(1)));
+
+        let mut record = GameRecord::new(start);
+        record.push(dice, BACKGAMMON);
+        assert_eq!(
+            analyse(&bearoff, &record),
+            Err(AnalysisError::IllegalMove(0))
+        );
+    }
+
+    #[test]
+    fn luck_of_recorded_game() {
+        let bearoff = TwoSidedBearoff::generate(2);
+        // only doubles from 3-3 up bear off both checkers before the opponent
+        let start = pos!(x 6:2; o 24:1);
+        let dice = Dice::new(6, 6);
+        let win = start.possible_positions(&dice)[0];
+        let mut record = GameRecord::new(start);
+        record.push(dice, win);
+        let report = luck(&bearoff, &record).unwrap();
+        let expected = 1.0 - (4.0 - 32.0) / 36.0;
+        assert!((report.rolls[0].luck - expected).abs() < 1e-3);
+        assert_eq!(report.rolls[0].class, LuckClass::VeryLucky);
+        assert!((report.net() - expected).abs() < 1e-3);
+    }
+
+    #[test]
+    fn opening_roll_luck_averages_out() {
+        let evaluator = PubEval::new();
+        let mut all = 0.0;
+        let mut opening = 0.0;
+        for (dice, n) in ALL_21 {
+            all += roll_luck(&evaluator, &BACKGAMMON, &dice) * n as f32 / 36.0;
+            if !is_double(&dice) {
+                opening += opening_roll_luck(&evaluator, &BACKGAMMON, &dice) * n as f32 / 30.0;
+            }
+        }
+        assert!(all.abs() < 1e-3);
+        assert!(opening.abs() < 1e-3);
+
+        let dice = Dice::new(3, 1);
+        let mut record = GameRecord::new(BACKGAMMON).with_opening_roll();
+        record.push(dice, evaluator.best_position(&BACKGAMMON, &dice));
+        let report = luck(&evaluator, &record).unwrap();
+        let expected = opening_roll_luck(&evaluator, &BACKGAMMON, &dice);
+        assert_eq!(report.rolls[0].luck, expected);
+        assert_ne!(expected, roll_luck(&evaluator, &BACKGAMMON, &dice));
+    }
+}
@@ -0,0 +1,118 @@
+mod onesided;
+mod twosided;
+
+pub use onesided::OneSidedBearoff;
+pub use twosided::TwoSidedBearoff;
+
+use bkgm::State;
+
+/// Checkers on the six home points of one player, `board[0]` being the 1-point.
+pub type Board = [u8; 6];
+
+/// All 21 rolls as die values, together with the number of ways to roll them.
+pub(crate) fn dice_rolls() -> impl Iterator<Item = (usize, usize, f32)> {
+    (1..=6).flat_map(|a| (a..=6).map(move |b| (a, b, if a == b { 1.0 } else { 2.0 })))
+}
+
+/// Home boards of the player on roll and of the opponent, `None` unless all checkers
+/// of both sides are in their home board or borne off.
+pub fn home_boards<G: State>(pos: &G) -> Option<(Board, Board)> {
+    if pos.x_bar() > 0 || pos.o_bar() > 0 {
+        return None;
+    }
+    let mut x = [0; 6];
+    let mut o = [0; 6];
+    for i in 1..=24 {
+        let pip = pos.pip(i);
+        if pip > 0 {
+            if i > 6 {
+                return None;
+            }
+            x[i - 1] = pip as u8;
+        } else if pip < 0 {
+            if i < 19 {
+                return None;
+            }
+            o[24 - i] = -pip as u8;
+        }
+    }
+    Some((x, o))
+}
+
+pub fn checkers(board: &Board) -> u8 {
+    board.iter().sum()
+}
+
+pub fn pips(board: &Board) -> usize {
+    board
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (i + 1) * *n as usize)
+        .sum()
+}
+
+/// Moves a single checker `die` pips, bearing off from the highest point if no checker is on or above `die`.
+fn play_die(board: &Board, die: usize) -> Vec<Board> {
+    let highest = match board.iter().rposition(|n| *n > 0) {
+        Some(highest) => highest + 1,
+        None => return vec![*board],
+    };
+    let mut boards = Vec::new();
+    for point in 1..=6 {
+        if board[point - 1] == 0 {
+            continue;
+        }
+        let mut board = *board;
+        if point > die {
+            board[point - 1] -= 1;
+            board[point - die - 1] += 1;
+        } else if point == die || point == highest {
+            board[point - 1] -= 1;
+        } else {
+            continue;
+        }
+        boards.push(board);
+    }
+    boards
+}
+
+/// All boards reachable with a roll. Without contact every die can always be played.
+pub fn moves(board: &Board, die1: usize, die2: usize) -> Vec<Board> {
+    let sequences: Vec<Vec<usize>> = if die1 == die2 {
+        vec![vec![die1; 4]]
+    } else {
+        vec![vec![die1, die2], vec![die2, die1]]
+    };
+    let mut boards = Vec::new();
+    for dice in sequences {
+        let mut current = vec![*board];
+        for die in dice {
+            current = current
+                .iter()
+                .flat_map(|board| play_die(board, die))
+                .collect();
+            current.sort_unstable();
+            current.dedup();
+        }
+        boards.extend(current);
+    }
+    boards.sort_unstable();
+    boards.dedup();
+    boards
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bearoff::moves;
+
+    #[test]
+    fn bear_off_from_highest_point() {
+        // 6-5 bears off both checkers on the 4-point
+        assert!(moves(&[0, 1, 0, 2, 0, 0], 6, 5).contains(&[0, 1, 0, 0, 0, 0]));
+        assert_eq!(moves(&[0, 0, 0, 0, 0, 0], 1, 1), vec![[0; 6]]);
+        // 2-1 with a checker on the 6-point can't bear off
+        assert!(moves(&[0, 0, 0, 0, 0, 1], 2, 1)
+            .iter()
+            .all(|board| board.iter().sum::<u8>() == 1));
+    }
+}
@@ -0,0 +1,353 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::evaluator::{Evaluator, PartialEvaluator};
+use crate::probabilities::Probabilities;
+use bkgm::State;
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use super::{checkers, dice_rolls, home_boards, moves, pips, Board};
+
+/// Number of rolls tracked per distribution, the last slot also holds all longer bearoffs.
+pub const SLOTS: usize = 32;
+pub const MAX_CHECKERS: u8 = 15;
+
+type Distribution = [f32; SLOTS];
+
+fn binomial(n: usize, k: usize) -> usize {
+    (0..k).fold(1, |acc, i| acc * (n - i) / (i + 1))
+}
+
+/// Number of boards with at most `checkers` checkers on `points` points.
+fn count(points: usize, checkers: usize) -> usize {
+    binomial(checkers + points, points)
+}
+
+fn mean(distribution: &Distribution) -> f32 {
+    distribution
+        .iter()
+        .enumerate()
+        .map(|(n, p)| n as f32 * p)
+        .sum()
+}
+
+/// One-sided bearoff database: for every home board of up to `max_checkers` checkers,
+/// the distribution of the number of rolls needed to bear off all checkers and
+/// to bear off the first checker, both when playing to minimise the expected number of rolls.
+pub struct OneSidedBearoff {
+    max_checkers: u8,
+    rolls: Vec<Distribution>,
+    gammon: Vec<Distribution>,
+}
+
+impl<G: State> PartialEvaluator<G> for OneSidedBearoff {
+    fn try_eval(&self, pos: &G) -> f32 {
+        self.eval(pos).equity()
+    }
+}
+
+impl<G: State> Evaluator<G> for OneSidedBearoff {
+    /// Panics unless `contains(pos)`.
+    fn eval(&self, pos: &G) -> Probabilities {
+        assert!(
+            self.contains(pos),
+            "position is not a bearoff of up to {} checkers",
+            self.max_checkers
+        );
+        let (x, o) = home_boards(pos).unwrap();
+        self.probabilities(&x, &o, pos.x_off() == 0, pos.o_off() == 0)
+    }
+}
+
+impl OneSidedBearoff {
+    pub fn new() -> Option<Self> {
+        Self::from_file("data/bearoff1.db")
+    }
+
+    /// Number of boards with up to `max_checkers` checkers.
+    pub fn size(max_checkers: u8) -> usize {
+        count(6, max_checkers as usize)
+    }
+
+    /// Index of a board among all boards with up to `max_checkers` checkers.
+    pub fn index(board: &Board, max_checkers: u8) -> usize {
+        let mut remaining = max_checkers as usize;
+        let mut index = 0;
+        for (point, n) in board.iter().enumerate() {
+            for v in 0..*n as usize {
+                index += count(5 - point, remaining - v);
+            }
+            remaining -= *n as usize;
+        }
+        index
+    }
+
+    /// All boards with up to `max_checkers` checkers, ordered by index.
+    pub fn boards(max_checkers: u8) -> Vec<Board> {
+        fn fill(board: &mut Board, point: usize, remaining: u8, boards: &mut Vec<Board>) {
+            if point == 6 {
+                boards.push(*board);
+                return;
+            }
+            for n in 0..=remaining {
+                board[point] = n;
+                fill(board, point + 1, remaining - n, boards);
+            }
+            board[point] = 0;
+        }
+        let mut boards = Vec::with_capacity(Self::size(max_checkers));
+        fill(&mut [0; 6], 0, max_checkers, &mut boards);
+        boards
+    }
+
+    /// Calculates the database, pip count by pip count as every move lowers the pip count.
+    pub fn generate(max_checkers: u8) -> Self {
+        assert!(max_checkers <= MAX_CHECKERS, "at most 15 checkers");
+        let boards = Self::boards(max_checkers);
+        let size = boards.len();
+        let mut rolls = vec![[0.0; SLOTS]; size];
+        let mut gammon = vec![[0.0; SLOTS]; size];
+        rolls[0][0] = 1.0;
+        gammon[0][0] = 1.0;
+
+        let mut levels = vec![Vec::new(); pips(&[0, 0, 0, 0, 0, max_checkers]) + 1];
+        for board in boards.iter().skip(1) {
+            levels[pips(board)].push(*board);
+        }
+
+        for level in levels {
+            let results: Vec<(usize, Distribution, Distribution)> = level
+                .par_iter()
+                .map(|board| {
+                    let mut board_rolls = [0.0; SLOTS];
+                    let mut board_gammon = [0.0; SLOTS];
+                    for (die1, die2, n) in dice_rolls() {
+                        let prob = n / 36.0;
+                        let children = moves(board, die1, die2);
+
+                        let best = children
+                            .iter()
+                            .map(|child| &rolls[Self::index(child, max_checkers)])
+                            .min_by(|a, b| mean(a).partial_cmp(&mean(b)).unwrap())
+                            .unwrap();
+                        shift_add(&mut board_rolls, best, prob);
+
+                        if children
+                            .iter()
+                            .any(|child| checkers(child) < checkers(board))
+                        {
+                            board_gammon[1] += prob;
+                        } else {
+                            let best = children
+                                .iter()
+                                .map(|child| &gammon[Self::index(child, max_checkers)])
+                                .min_by(|a, b| mean(a).partial_cmp(&mean(b)).unwrap())
+                                .unwrap();
+                            shift_add(&mut board_gammon, best, prob);
+                        }
+                    }
+                    (Self::index(board, max_checkers), board_rolls, board_gammon)
+                })
+                .collect();
+            for (index, board_rolls, board_gammon) in results {
+                rolls[index] = board_rolls;
+                gammon[index] = board_gammon;
+            }
+        }
+
+        Self {
+            max_checkers,
+            rolls,
+            gammon,
+        }
+    }
+
+    pub fn max_checkers(&self) -> u8 {
+        self.max_checkers
+    }
+
+    /// Both sides have all checkers home and at most `max_checkers` checkers left.
+    pub fn contains<G: State>(&self, pos: &G) -> bool {
+        match home_boards(pos) {
+            Some((x, o)) => checkers(&x) <= self.max_checkers && checkers(&o) <= self.max_checkers,
+            None => false,
+        }
+    }
+
+    /// Probability of needing exactly `n` rolls to bear off all checkers, for every `n`.
+    pub fn distribution(&self, board: &Board) -> &[f32; SLOTS] {
+        &self.rolls[Self::index(board, self.max_checkers)]
+    }
+
+    /// Probability of needing exactly `n` rolls to bear off the first checker, for every `n`.
+    pub fn gammon_distribution(&self, board: &Board) -> &[f32; SLOTS] {
+        &self.gammon[Self::index(board, self.max_checkers)]
+    }
+
+    /// Expected number of rolls to bear off all checkers.
+    pub fn mean_rolls(&self, board: &Board) -> f32 {
+        mean(self.distribution(board))
+    }
+
+    /// Probabilities of the player on roll with home board `x` against `o`, both sides
+    /// playing to minimise their expected number of rolls.
+    /// Gammons are only possible for a side that hasn't borne off any checker yet.
+    pub fn probabilities(
+        &self,
+        x: &Board,
+        o: &Board,
+        x_gammonable: bool,
+        o_gammonable: bool,
+    ) -> Probabilities {
+        let x_rolls = self.distribution(x);
+        let o_rolls = self.distribution(o);
+        // at_least[n] is the probability of needing n or more rolls
+        let at_least = |distribution: &Distribution| {
+            let mut at_least = [0.0; SLOTS + 1];
+            for n in (0..SLOTS).rev() {
+                at_least[n] = at_least[n + 1] + distribution[n];
+            }
+            at_least
+        };
+
+        // the player on roll finishes first with as many rolls as the opponent
+        let o_at_least = at_least(o_rolls);
+        let win: f32 = (0..SLOTS).map(|n| x_rolls[n] * o_at_least[n]).sum();
+        let win_g: f32 = if o_gammonable {
+            let o_first = at_least(self.gammon_distribution(o));
+            (0..SLOTS).map(|n| x_rolls[n] * o_first[n]).sum()
+        } else {
+            0.0
+        };
+        let lose_g: f32 = if x_gammonable {
+            let x_first = at_least(self.gammon_distribution(x));
+            (0..SLOTS).map(|n| o_rolls[n] * x_first[n + 1]).sum()
+        } else {
+            0.0
+        };
+
+        Probabilities {
+            win_n: win - win_g,
+            win_g,
+            win_b: 0.0,
+            lose_n: 1.0 - win - lose_g,
+            lose_g,
+            lose_b: 0.0,
+        }
+    }
+
+    /// Writes the number of checkers followed by both distributions of every board as little endian floats.
+    pub fn to_file(&self, file_path: impl AsRef<Path>) -> io::Result<()> {
+        let file = File::create(file_path)?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(&[self.max_checkers])?;
+        for (rolls, gammon) in self.rolls.iter().zip(&self.gammon) {
+            for p in rolls.iter().chain(gammon) {
+                writer.write_all(&p.to_le_bytes())?;
+            }
+        }
+        writer.flush()
+    }
+
+    /// `None` unless the file holds exactly the boards of the number of checkers in its header.
+    pub fn from_file(file_path: impl AsRef<Path>) -> Option<Self> {
+        let file = File::open(file_path).ok()?;
+        let len = file.metadata().ok()?.len() as usize;
+        let mut reader = BufReader::new(file);
+
+        let mut header = [0u8; 1];
+        reader.read_exact(&mut header).ok()?;
+        let max_checkers = header[0];
+        if max_checkers > MAX_CHECKERS || len != 1 + Self::size(max_checkers) * 8 * SLOTS {
+            return None;
+        }
+
+        let mut buffer = [0u8; 4 * 2 * SLOTS];
+        let mut rolls = Vec::new();
+        let mut gammon = Vec::new();
+        for _ in 0..Self::size(max_checkers) {
+            reader.read_exact(&mut buffer).ok()?;
+            let values: Vec<f32> = buffer
+                .chunks_exact(4)
+                .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+                .collect();
+            rolls.push(values[..SLOTS].try_into().unwrap());
+            gammon.push(values[SLOTS..].try_into().unwrap());
+        }
+
+        Some(Self {
+            max_checkers,
+            rolls,
+            gammon,
+        })
+    }
+}
+
+/// Adds `prob` times `distribution` delayed by one roll.
+fn shift_add(target: &mut Distribution, distribution: &Distribution, prob: f32) {
+    for (n, p) in distribution.iter().enumerate() {
+        target[(n + 1).min(SLOTS - 1)] += prob * p;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::bearoff::OneSidedBearoff;
+    use crate::evaluator::Evaluator;
+    use bkgm::pos;
+
+    #[test]
+    fn index_matches_order() {
+        let boards = OneSidedBearoff::boards(4);
+        assert_eq!(boards.len(), OneSidedBearoff::size(4));
+        for (i, board) in boards.iter().enumerate() {
+            assert_eq!(OneSidedBearoff::index(board, 4), i);
+        }
+        assert_eq!(OneSidedBearoff::size(15), 54264);
+    }
+
+    #[test]
+    fn small_bearoffs() {
+        let db = OneSidedBearoff::generate(3);
+        assert!((db.distribution(&[1, 0, 0, 0, 0, 0])[1] - 1.0).abs() < 1e-6);
+        // two checkers on the 6-point need two rolls unless rolling a double of 3 or more
+        assert!((db.distribution(&[0, 0, 0, 0, 0, 2])[1] - 4.0 / 36.0).abs() < 1e-6);
+
+        let probs = db.probabilities(&[0, 0, 0, 0, 0, 3], &[0, 0, 0, 0, 0, 3], true, true);
+        assert!(probs.win_n + probs.win_g > 0.5);
+        let sum = probs.win_n + probs.win_g + probs.lose_n + probs.lose_g;
+        assert!((sum - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    #[should_panic(expected = "not a bearoff of up to 2 checkers")]
+    fn more_checkers_than_database() {
+        let db = OneSidedBearoff::generate(2);
+        db.eval(&pos!(x 1:3; o 24:1));
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let path = std::env::temp_dir().join("rassay-bearoff1-2.db");
+        let db = OneSidedBearoff::generate(2);
+        db.to_file(&path).unwrap();
+        let loaded = OneSidedBearoff::from_file(&path).unwrap();
+        assert_eq!(loaded.max_checkers(), 2);
+        assert_eq!(
+            loaded.distribution(&[0, 1, 0, 0, 0, 1]),
+            db.distribution(&[0, 1, 0, 0, 0, 1])
+        );
+
+        // a partial record at the end
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(&[0; 3]).unwrap();
+        assert!(OneSidedBearoff::from_file(&path).is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+}
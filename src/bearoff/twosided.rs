@@ -0,0 +1,232 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+use crate::evaluator::{Evaluator, PartialEvaluator};
+use crate::probabilities::Probabilities;
+use bkgm::State;
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use super::{checkers, dice_rolls, home_boards, moves, pips, Board, OneSidedBearoff};
+
+/// Win, gammon win and gammon loss probability of the player on roll.
+type Values = [f32; 3];
+
+fn equity(values: &Values) -> f32 {
+    2.0 * values[0] - 1.0 + values[1] - values[2]
+}
+
+/// Values of the position after a move, seen from the player who moved.
+fn after_move(child: &Values) -> Values {
+    [1.0 - child[0], child[2], child[1]]
+}
+
+fn quantize(p: f32) -> u16 {
+    (p.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16
+}
+
+fn dequantize(q: u16) -> f32 {
+    q as f32 / u16::MAX as f32
+}
+
+/// Two-sided bearoff database: exact cubeless probabilities, gammons included, for every
+/// pair of home boards with up to `max_checkers` checkers each, both sides maximising their equity.
+/// Gammons depend on whether a side has borne off any checker, hence on `G::NUM_CHECKERS`,
+/// the number of checkers each side starts the game with.
+pub struct TwoSidedBearoff<G: State> {
+    phantom: PhantomData<G>,
+    max_checkers: u8,
+    values: Vec<[u16; 3]>,
+}
+
+impl<G: State> PartialEvaluator<G> for TwoSidedBearoff<G> {
+    fn try_eval(&self, pos: &G) -> f32 {
+        self.eval(pos).equity()
+    }
+}
+
+impl<G: State> Evaluator<G> for TwoSidedBearoff<G> {
+    /// Panics unless `contains(pos)`.
+    fn eval(&self, pos: &G) -> Probabilities {
+        let (x, o) = home_boards(pos).expect("position is not a bearoff");
+        self.probabilities(&x, &o)
+    }
+}
+
+impl<G: State> TwoSidedBearoff<G> {
+    pub fn new() -> Option<Self> {
+        Self::from_file("data/bearoff2.db")
+    }
+
+    fn index(&self, x: &Board, o: &Board) -> usize {
+        let size = OneSidedBearoff::size(self.max_checkers);
+        OneSidedBearoff::index(x, self.max_checkers) * size
+            + OneSidedBearoff::index(o, self.max_checkers)
+    }
+
+    /// Calculates the database backwards from the finished games. Unlike the positions of `makedb`,
+    /// every move lowers the combined pip count, so sweeping the positions in order of pip count
+    /// gives the exact values in a single sweep instead of iterating until they converge.
+    pub fn generate(max_checkers: u8) -> Self {
+        let total_checkers = G::NUM_CHECKERS;
+        assert!(
+            max_checkers <= total_checkers,
+            "more checkers than in the game"
+        );
+        let boards = OneSidedBearoff::boards(max_checkers);
+        let size = boards.len();
+        let index = |x: &Board, o: &Board| {
+            OneSidedBearoff::index(x, max_checkers) * size + OneSidedBearoff::index(o, max_checkers)
+        };
+        let gammonable = |board: &Board| checkers(board) == total_checkers;
+
+        let mut values: Vec<Values> = vec![[0.0; 3]; size * size];
+        let mut levels = vec![Vec::new(); 2 * pips(&[0, 0, 0, 0, 0, max_checkers]) + 1];
+        for x in &boards {
+            for o in &boards {
+                if checkers(o) == 0 {
+                    // the opponent has just borne off the last checker
+                    values[index(x, o)] = [0.0, 0.0, if gammonable(x) { 1.0 } else { 0.0 }];
+                } else if checkers(x) > 0 {
+                    levels[pips(x) + pips(o)].push((*x, *o));
+                }
+            }
+        }
+
+        for level in levels {
+            let results: Vec<(usize, Values)> = level
+                .par_iter()
+                .map(|(x, o)| {
+                    let mut position = [0.0; 3];
+                    for (die1, die2, n) in dice_rolls() {
+                        let best = moves(x, die1, die2)
+                            .iter()
+                            .map(|child| match checkers(child) {
+                                0 => [1.0, if gammonable(o) { 1.0 } else { 0.0 }, 0.0],
+                                _ => after_move(&values[index(o, child)]),
+                            })
+                            .max_by(|a, b| equity(a).partial_cmp(&equity(b)).unwrap())
+                            .unwrap();
+                        for (value, best) in position.iter_mut().zip(best) {
+                            *value += n / 36.0 * best;
+                        }
+                    }
+                    (index(x, o), position)
+                })
+                .collect();
+            for (index, position) in results {
+                values[index] = position;
+            }
+        }
+
+        Self {
+            phantom: PhantomData,
+            max_checkers,
+            values: values.iter().map(|values| values.map(quantize)).collect(),
+        }
+    }
+
+    pub fn max_checkers(&self) -> u8 {
+        self.max_checkers
+    }
+
+    /// Both sides have all checkers home and at most `max_checkers` checkers left.
+    pub fn contains(&self, pos: &G) -> bool {
+        match home_boards(pos) {
+            Some((x, o)) => checkers(&x) <= self.max_checkers && checkers(&o) <= self.max_checkers,
+            None => false,
+        }
+    }
+
+    /// Probabilities of the player on roll with home board `x` against `o`.
+    pub fn probabilities(&self, x: &Board, o: &Board) -> Probabilities {
+        let [win, win_g, lose_g] = self.values[self.index(x, o)].map(dequantize);
+        Probabilities {
+            win_n: win - win_g,
+            win_g,
+            win_b: 0.0,
+            lose_n: 1.0 - win - lose_g,
+            lose_g,
+            lose_b: 0.0,
+        }
+    }
+
+    /// Writes both numbers of checkers followed by win, gammon win and gammon loss
+    /// of every position as little endian `u16` fractions of `u16::MAX`.
+    pub fn to_file(&self, file_path: impl AsRef<Path>) -> io::Result<()> {
+        let file = File::create(file_path)?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(&[self.max_checkers, G::NUM_CHECKERS])?;
+        for values in &self.values {
+            for value in values {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+        writer.flush()
+    }
+
+    /// `None` if the file is invalid or was generated for another number of checkers than `G::NUM_CHECKERS`.
+    pub fn from_file(file_path: impl AsRef<Path>) -> Option<Self> {
+        let file = File::open(file_path).ok()?;
+        let mut reader = BufReader::new(file);
+
+        let mut header = [0u8; 2];
+        reader.read_exact(&mut header).ok()?;
+        let [max_checkers, total_checkers] = header;
+        if total_checkers != G::NUM_CHECKERS || max_checkers > total_checkers {
+            return None;
+        }
+
+        let mut buffer = [0u8; 6];
+        let mut values = Vec::new();
+        while reader.read_exact(&mut buffer).is_ok() {
+            values.push([
+                u16::from_le_bytes([buffer[0], buffer[1]]),
+                u16::from_le_bytes([buffer[2], buffer[3]]),
+                u16::from_le_bytes([buffer[4], buffer[5]]),
+            ]);
+        }
+
+        if values.len() == OneSidedBearoff::size(max_checkers).pow(2) {
+            Some(Self {
+                phantom: PhantomData,
+                max_checkers,
+                values,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bearoff::TwoSidedBearoff;
+    use bkgm::Position;
+
+    #[test]
+    fn last_checkers() {
+        let db = TwoSidedBearoff::<Position<2>>::generate(2);
+        // a single checker on the 1-point always wins, with a gammon against two checkers
+        let probs = db.probabilities(&[1, 0, 0, 0, 0, 0], &[0, 0, 0, 0, 0, 2]);
+        assert!((probs.win_g - 1.0).abs() < 1e-4);
+        let probs = db.probabilities(&[1, 0, 0, 0, 0, 0], &[1, 0, 0, 0, 0, 0]);
+        assert!((probs.win_n - 1.0).abs() < 1e-4);
+
+        // two checkers on the 6-point only win with a big double against one on the 1-point
+        let probs = db.probabilities(&[0, 0, 0, 0, 0, 2], &[1, 0, 0, 0, 0, 0]);
+        assert!((probs.win_n + probs.win_g - 4.0 / 36.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn rejects_other_number_of_checkers() {
+        let path = std::env::temp_dir().join("rassay-bearoff2.db");
+        TwoSidedBearoff::<Position<2>>::generate(2)
+            .to_file(&path)
+            .unwrap();
+        assert!(TwoSidedBearoff::<Position<2>>::from_file(&path).is_some());
+        assert!(TwoSidedBearoff::<Position<3>>::from_file(&path).is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
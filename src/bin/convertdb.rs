@@ -0,0 +1,70 @@
+use clap::Parser;
+use rassay::evaluator::database::read_raw;
+use rassay::evaluator::{DatabaseError, DatabaseValue, HyperEvaluator, Metadata};
+use rassay::probabilities::Probabilities;
+use std::path::PathBuf;
+
+/// Convert a headerless database to the versioned format
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Headerless database
+    input: PathBuf,
+
+    /// Output file
+    output: PathBuf,
+
+    /// Number of checkers per side, 2 to 5
+    #[arg(short = 'c', long = "checkers", default_value = "3", value_parser = clap::value_parser!(u8).range(2..=5))]
+    checkers: u8,
+
+    /// Input holds equities, as written by makedb, instead of probabilities
+    #[arg(short = 'e', long = "equities")]
+    equities: bool,
+
+    /// Number of iterations the database was generated with, if known
+    #[arg(short = 'i', long = "iter", default_value = "0")]
+    iterations: u32,
+
+    /// Discount factor the database was generated with
+    #[arg(short = 'y', long = "discount", default_value = "1.0")]
+    discount: f32,
+
+    /// Largest change of a value in the last iteration, if known
+    #[arg(short = 'd', long = "delta", default_value = "0.0")]
+    max_error: f32,
+}
+
+fn convert<const N: u8, V: DatabaseValue>(args: &Args) -> Result<(), DatabaseError> {
+    let values: Vec<V> = read_raw(&args.input, HyperEvaluator::<N, V>::possible())?;
+    let metadata = Metadata {
+        iterations: args.iterations,
+        discount: args.discount,
+        max_error: args.max_error,
+    };
+    HyperEvaluator::<N, V>::write(&args.output, &values, metadata)?;
+    // Read back to make sure the written file is valid
+    HyperEvaluator::<N, V>::from_file_verified(&args.output)?;
+    Ok(())
+}
+
+fn run<const N: u8>(args: &Args) -> Result<(), DatabaseError> {
+    match args.equities {
+        true => convert::<N, f32>(args),
+        false => convert::<N, Probabilities>(args),
+    }
+}
+
+fn main() -> Result<(), DatabaseError> {
+    let args = Args::parse();
+    match args.checkers {
+        2 => run::<2>(&args)?,
+        3 => run::<3>(&args)?,
+        4 => run::<4>(&args)?,
+        5 => run::<5>(&args)?,
+        _ => unreachable!("clap only accepts 2 to 5 checkers"),
+    }
+    println!("Written to {}", args.output.display());
+    Ok(())
+}
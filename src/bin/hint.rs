@@ -0,0 +1,71 @@
+use bkgm::{Dice, Position};
+use burn::backend::libtorch::{LibTorch, LibTorchDevice};
+use clap::Parser;
+use rassay::evaluator::{Evaluator, PlyEvaluator};
+use rassay::hint::hint;
+use rassay::model::{EquityModel, TDModel};
+use std::path::PathBuf;
+
+/// Rank the moves of a position for a roll
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// gnubg position ID, from the perspective of the player on roll
+    position: String,
+
+    /// First die
+    die1: usize,
+
+    /// Second die
+    die2: usize,
+
+    /// Model
+    #[arg(short = 'm', long = "model")]
+    model: PathBuf,
+
+    /// Hidden layer size of the model
+    #[arg(short = 's', long = "size", default_value = "160")]
+    size: usize,
+
+    /// Search depth
+    #[arg(short = 'p', long = "ply", default_value = "0")]
+    ply: usize,
+
+    /// Number of moves to show
+    #[arg(short = 'n', long = "moves", default_value = "10")]
+    moves: usize,
+}
+
+fn print_hints<E: Evaluator<Position<15>>>(evaluator: &E, args: &Args) {
+    let position = Position::<15>::from_id(&args.position);
+    let dice = Dice::new(args.die1, args.die2);
+    let hints = hint(evaluator, &position, &dice);
+    println!("{} moves", hints.len());
+    for (rank, hint) in hints.iter().take(args.moves).enumerate() {
+        let probs = hint.probabilities;
+        println!(
+            "{:>3}. {:<24} {:>7.3} ({:>7.3})  {:.1}% {:.1}% {:.1}% - {:.1}% {:.1}% {:.1}%",
+            rank + 1,
+            hint.notation,
+            hint.equity,
+            -hint.equity_loss,
+            probs.win_n * 100.0,
+            probs.win_g * 100.0,
+            probs.win_b * 100.0,
+            probs.lose_n * 100.0,
+            probs.lose_g * 100.0,
+            probs.lose_b * 100.0,
+        );
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let model = TDModel::<LibTorch>::init_with(LibTorchDevice::Cpu, &args.model, args.size);
+    if args.ply == 0 {
+        print_hints(&model, &args);
+    } else {
+        print_hints(&PlyEvaluator::new(model, args.ply), &args);
+    }
+}
@@ -0,0 +1,74 @@
+use bkgm::{Position, State};
+use clap::Parser;
+use rassay::bearoff::{OneSidedBearoff, TwoSidedBearoff};
+use std::{io, path::PathBuf};
+
+/// Make one-sided or two-sided bearoff database
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Output file, data/bearoff1.db or data/bearoff2.db by default
+    #[arg(short = 'f', long = "file")]
+    file: Option<PathBuf>,
+
+    /// Maximum number of checkers per side
+    #[arg(short = 'c', long = "checkers")]
+    checkers: Option<u8>,
+
+    /// Two-sided database
+    #[arg(short = 't', long = "two-sided")]
+    two_sided: bool,
+
+    /// Number of checkers each side starts with, decides gammons in the two-sided database:
+    /// 15 for backgammon, 2 to 5 for Hypergammon
+    #[arg(short = 'n', long = "total", default_value = "15")]
+    total: u8,
+}
+
+fn two_sided<G: State>(checkers: u8, file: &PathBuf) -> io::Result<()> {
+    let start = std::time::Instant::now();
+    let db = TwoSidedBearoff::<G>::generate(checkers);
+    println!(
+        "Positions: {} Time: {:?}",
+        OneSidedBearoff::size(checkers).pow(2),
+        start.elapsed()
+    );
+    db.to_file(file)
+}
+
+fn main() -> io::Result<()> {
+    let args = Args::parse();
+
+    if args.two_sided {
+        let checkers = args.checkers.unwrap_or(6);
+        let file = args.file.unwrap_or(PathBuf::from("data/bearoff2.db"));
+        match args.total {
+            2 => two_sided::<Position<2>>(checkers, &file)?,
+            3 => two_sided::<Position<3>>(checkers, &file)?,
+            4 => two_sided::<Position<4>>(checkers, &file)?,
+            5 => two_sided::<Position<5>>(checkers, &file)?,
+            15 => two_sided::<Position<15>>(checkers, &file)?,
+            total => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("no variant with {total} checkers"),
+                ))
+            }
+        }
+        println!("Written to {}", file.display());
+    } else {
+        let start = std::time::Instant::now();
+        let checkers = args.checkers.unwrap_or(15);
+        let db = OneSidedBearoff::generate(checkers);
+        println!(
+            "Boards: {} Time: {:?}",
+            OneSidedBearoff::size(checkers),
+            start.elapsed()
+        );
+        let file = args.file.unwrap_or(PathBuf::from("data/bearoff1.db"));
+        db.to_file(&file)?;
+        println!("Written to {}", file.display());
+    }
+    Ok(())
+}
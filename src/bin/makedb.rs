@@ -1,25 +1,36 @@
-use std::{iter::zip, sync::Arc};
+use std::{io, iter::zip, path::PathBuf, sync::Arc};
 
 use bkgm::{
     dice::{ALL_21, ALL_SINGLES},
-    utils::mcomb,
-    GameState, Position, State, HYPERGAMMON, HYPERGAMMON2, HYPERGAMMON4,
+    GameState, Position, State, HYPERGAMMON, HYPERGAMMON2, HYPERGAMMON4, HYPERGAMMON5,
 };
+use clap::Parser;
 use crossbeam::thread;
 use dashmap::DashSet;
 use indicatif::{ParallelProgressIterator, ProgressStyle};
+use rassay::evaluator::{Metadata, SubHyperEvaluator};
 use rayon::{
     iter::ParallelBridge,
     prelude::{IntoParallelRefIterator, ParallelIterator},
 };
 
-const STYLE: &str =
-    "{wide_bar} {pos}/{len} ({percent}%) Elapsed: {elapsed_precise} ETA: {eta_precise}";
+/// Make equity database of a Hypergammon variant
 
-fn num_possible(checkers: u8) -> usize {
-    mcomb(26, checkers as usize).pow(2)
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Number of checkers per side, 2 to 5
+    #[arg(short = 'c', long = "checkers", default_value = "4", value_parser = clap::value_parser!(u8).range(2..=5))]
+    checkers: u8,
+
+    /// Output file, data/hyper{checkers}-equity.db by default
+    #[arg(short = 'f', long = "file")]
+    file: Option<PathBuf>,
 }
 
+const STYLE: &str =
+    "{wide_bar} {pos}/{len} ({percent}%) Elapsed: {elapsed_precise} ETA: {eta_precise}";
+
 fn unique<const N: u8>(position: &Position<N>, verbose: bool) -> Vec<Position<N>> {
     let num_threads = std::thread::available_parallelism().unwrap().get();
 
@@ -121,7 +132,7 @@ fn split_positions<const N: u8>(
 }
 
 fn initial_equities<const N: u8>(gameover: Vec<Position<N>>) -> Vec<f32> {
-    let mut equities = vec![0.0; num_possible(N)];
+    let mut equities = vec![0.0; SubHyperEvaluator::<N>::possible()];
     gameover.iter().for_each(|p| {
         equities[p.dbhash()] = match &p.game_state() {
             GameState::Ongoing => panic!("Should not be ongoing"),
@@ -359,7 +370,7 @@ fn create_posmap<const N: u8>(ongoing: &Vec<Position<N>>) -> Vec<(u32, Vec<(f32,
     posmap
 }
 
-fn run<const N: u8>(position: &Position<N>) {
+fn run<const N: u8>(position: &Position<N>, file: &PathBuf) -> io::Result<()> {
     let start = std::time::Instant::now();
     let positions = unique(position, true);
     let elapsed = start.elapsed();
@@ -369,7 +380,7 @@ fn run<const N: u8>(position: &Position<N>) {
 
     println!(
         "Posssible: {} Reachable: {} Ongoing: {} Gameover: {}",
-        num_possible(N),
+        SubHyperEvaluator::<N>::possible(),
         reachable,
         ongoing.len(),
         gameover.len()
@@ -409,8 +420,28 @@ fn run<const N: u8>(position: &Position<N>) {
     let total_elapsed = start.elapsed();
     println!("Total Time: {:?}", total_elapsed);
     println!("Iteration Time: {:?}", iteration_elapsed);
+
+    let metadata = Metadata {
+        iterations: iteration,
+        discount: 1.0,
+        max_error: max_err,
+    };
+    SubHyperEvaluator::<N>::write(file, &equities, metadata)?;
+    println!("Written to {}", file.display());
+    Ok(())
 }
 
-fn main() {
-    run(&HYPERGAMMON4);
+fn main() -> io::Result<()> {
+    let args = Args::parse();
+    let file = args.file.unwrap_or(PathBuf::from(format!(
+        "data/hyper{}-equity.db",
+        args.checkers
+    )));
+    match args.checkers {
+        2 => run(&HYPERGAMMON2, &file),
+        3 => run(&HYPERGAMMON, &file),
+        4 => run(&HYPERGAMMON4, &file),
+        5 => run(&HYPERGAMMON5, &file),
+        _ => unreachable!("clap only accepts 2 to 5 checkers"),
+    }
 }
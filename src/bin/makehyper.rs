@@ -6,6 +6,7 @@ use bkgm::{
 };
 use clap::Parser;
 use indicatif::{ParallelProgressIterator, ProgressStyle};
+use rassay::evaluator::{HyperEvaluator, Metadata};
 use rassay::probabilities::Probabilities;
 use rayon::{
     iter::ParallelBridge,
@@ -80,24 +81,8 @@ fn write_unique(args: &Args, unique: &Vec<Hypergammon>) -> io::Result<()> {
     buf_writer.flush()
 }
 
-fn write_file(args: &Args, probs: &[Probabilities]) -> io::Result<()> {
-    let file = File::create(&args.file)?;
-    let mut buf_writer = BufWriter::new(file);
-
-    for prob in probs.iter() {
-        let wgbgb = [
-            prob.win_n + prob.win_g + prob.win_b,
-            prob.win_g + prob.win_b,
-            prob.win_b,
-            prob.lose_g + prob.lose_b,
-            prob.lose_b,
-        ];
-        for wgbgb in wgbgb.iter() {
-            buf_writer.write_all(&wgbgb.to_le_bytes())?;
-        }
-    }
-
-    buf_writer.flush()
+fn write_file(args: &Args, probs: &[Probabilities], metadata: Metadata) -> io::Result<()> {
+    HyperEvaluator::<3>::write(&args.file, probs, metadata)
 }
 
 const POSSIBLE: usize = mcomb(26, Hypergammon::NUM_CHECKERS as usize).pow(2);
@@ -458,7 +443,7 @@ fn run(args: &Args) -> io::Result<()> {
     let posmap = create_posmap(ongoing);
     println!("Position Map Created");
     let mut avg_err;
-    let mut max_err;
+    let mut max_err = 0.0;
     let mut equities = initial_equities(gameover);
     for iteration in 0..args.iterations {
         (equities, avg_err, max_err) = iterate_probabilities(&posmap, &equities);
@@ -483,7 +468,13 @@ fn run(args: &Args) -> io::Result<()> {
         // );
     }
     println!("Writing to {}", args.file.display());
-    write_file(&args, &equities)
+    let metadata = Metadata {
+        iterations: args.iterations as u32,
+        // Only equities are discounted
+        discount: 1.0,
+        max_error: max_err,
+    };
+    write_file(&args, &equities, metadata)
 }
 
 fn main() -> io::Result<()> {
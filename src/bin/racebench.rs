@@ -0,0 +1,88 @@
+use clap::Parser;
+use rassay::bearoff::{Board, OneSidedBearoff};
+use rassay::evaluator::RaceEvaluator;
+use std::path::PathBuf;
+
+/// Compare the race evaluator against the one-sided bearoff database
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// One-sided bearoff database, generated if it can't be read
+    #[arg(short = 'f', long = "file", default_value = "data/bearoff1.db")]
+    file: PathBuf,
+
+    /// Number of random bearoff positions
+    #[arg(short = 's', long = "samples", default_value = "100000")]
+    samples: usize,
+
+    /// Seed
+    #[arg(long = "seed", default_value = "0")]
+    seed: u64,
+}
+
+struct Errors {
+    name: &'static str,
+    sum: f32,
+    max: f32,
+}
+
+impl Errors {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            sum: 0.0,
+            max: 0.0,
+        }
+    }
+
+    fn add(&mut self, error: f32) {
+        self.sum += error.abs();
+        self.max = self.max.max(error.abs());
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let db = OneSidedBearoff::from_file(&args.file).unwrap_or_else(|| {
+        println!("Generating one-sided bearoff database");
+        OneSidedBearoff::generate(15)
+    });
+    let boards: Vec<Board> = OneSidedBearoff::boards(db.max_checkers())
+        .into_iter()
+        .skip(1)
+        .collect();
+
+    let mut rng = fastrand::Rng::with_seed(args.seed);
+    let mut estimated = Errors::new("Estimated EPC");
+    let mut exact = Errors::new("Bearoff EPC");
+    for _ in 0..args.samples {
+        let x = boards[rng.usize(..boards.len())];
+        let o = boards[rng.usize(..boards.len())];
+        let probs = db.probabilities(&x, &o, false, false);
+        let win = probs.win_n;
+
+        let estimate = RaceEvaluator::win_probability(
+            RaceEvaluator::estimated_board_effective(&x),
+            RaceEvaluator::estimated_board_effective(&o),
+        );
+        estimated.add(estimate - win);
+
+        let estimate = RaceEvaluator::win_probability(
+            db.mean_rolls(&x) * 49.0 / 6.0,
+            db.mean_rolls(&o) * 49.0 / 6.0,
+        );
+        exact.add(estimate - win);
+    }
+
+    println!("Samples: {}", args.samples);
+    for errors in [estimated, exact] {
+        println!(
+            "{}\tMean Error: {:.4}\tMax Error: {:.4}",
+            errors.name,
+            errors.sum / args.samples as f32,
+            errors.max
+        );
+    }
+}
@@ -0,0 +1,289 @@
+use std::fmt;
+
+use crate::probabilities::Probabilities;
+
+/// Cube efficiency used by gnubg for money game evaluations.
+pub const DEFAULT_CUBE_EFFICIENCY: f32 = 0.68;
+
+/// Owner of the doubling cube, relative to the player on roll.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum CubeOwner {
+    Centered,
+    Player,
+    Opponent,
+}
+
+impl CubeOwner {
+    pub fn flip(&self) -> Self {
+        match self {
+            CubeOwner::Centered => CubeOwner::Centered,
+            CubeOwner::Player => CubeOwner::Opponent,
+            CubeOwner::Opponent => CubeOwner::Player,
+        }
+    }
+}
+
+/// State of the doubling cube in a money game, seen from the player on roll.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct CubeState {
+    pub value: u32,
+    pub owner: CubeOwner,
+    /// Gammons and backgammons only count once the cube has been turned.
+    pub jacoby: bool,
+    /// The taker may immediately redouble while keeping the cube.
+    pub beavers: bool,
+}
+
+impl Default for CubeState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CubeState {
+    /// Centered cube on 1 without Jacoby rule or beavers.
+    pub fn new() -> Self {
+        Self {
+            value: 1,
+            owner: CubeOwner::Centered,
+            jacoby: false,
+            beavers: false,
+        }
+    }
+
+    pub fn with_jacoby(self, jacoby: bool) -> Self {
+        Self { jacoby, ..self }
+    }
+
+    pub fn with_beavers(self, beavers: bool) -> Self {
+        Self { beavers, ..self }
+    }
+
+    /// Cube state as seen by the opponent.
+    pub fn flip(&self) -> Self {
+        Self {
+            owner: self.owner.flip(),
+            ..*self
+        }
+    }
+
+    /// Whether the player on roll is allowed to double.
+    pub fn can_double(&self) -> bool {
+        self.owner != CubeOwner::Opponent
+    }
+
+    /// Cube state after the player on roll doubled and the opponent took.
+    pub fn doubled(&self) -> Self {
+        Self {
+            value: self.value * 2,
+            owner: CubeOwner::Opponent,
+            ..*self
+        }
+    }
+
+    /// Cubeful equity of the player on roll in points, so scaled by the cube value.
+    ///
+    /// Uses Janowski's interpolation between the dead cube (cubeless) equity and the
+    /// live cube equity, `cube_efficiency` being the weight of the live cube.
+    pub fn cubeful_equity(&self, probs: &Probabilities, cube_efficiency: f32) -> f32 {
+        let dead = if self.jacoby && self.owner == CubeOwner::Centered {
+            probs.win_prob() - (1.0 - probs.win_prob())
+        } else {
+            probs.equity()
+        };
+        let live = self.live_equity(probs);
+        self.value as f32 * (dead * (1.0 - cube_efficiency) + live * cube_efficiency)
+    }
+
+    /// Equity of the player on roll if the cube was fully live, normalised to the cube value.
+    fn live_equity(&self, probs: &Probabilities) -> f32 {
+        let p = probs.win_prob();
+        let (win, lose) = average_values(probs);
+        let take_point = (lose - 0.5) / (win + lose + 0.5);
+        let cash_point = (lose + 1.0) / (win + lose + 0.5);
+
+        match self.owner {
+            CubeOwner::Centered => {
+                if p < take_point {
+                    if self.jacoby {
+                        -1.0
+                    } else {
+                        -lose + (lose - 1.0) * p / take_point
+                    }
+                } else if p < cash_point {
+                    -1.0 + 2.0 * (p - take_point) / (cash_point - take_point)
+                } else if self.jacoby {
+                    1.0
+                } else {
+                    1.0 + (win - 1.0) * (p - cash_point) / (1.0 - cash_point)
+                }
+            }
+            CubeOwner::Player => {
+                if p < cash_point {
+                    -lose + (1.0 + lose) * p / cash_point
+                } else {
+                    1.0 + (win - 1.0) * (p - cash_point) / (1.0 - cash_point)
+                }
+            }
+            CubeOwner::Opponent => {
+                if p < take_point {
+                    -lose + (lose - 1.0) * p / take_point
+                } else {
+                    -1.0 + (win + 1.0) * (p - take_point) / (1.0 - take_point)
+                }
+            }
+        }
+    }
+
+    /// Double/take/pass analysis for the player on roll.
+    /// All equities are in points, so scaled by the cube value.
+    pub fn decision(&self, probs: &Probabilities, cube_efficiency: f32) -> CubeDecision {
+        let no_double = self.cubeful_equity(probs, cube_efficiency);
+        let mut double_take = self.doubled().cubeful_equity(probs, cube_efficiency);
+        let double_pass = self.value as f32;
+
+        // After a beaver the cube is on four times its value and still owned by the taker.
+        let beaver = self.beavers && double_take < 0.0;
+        if beaver {
+            double_take *= 2.0;
+        }
+
+        let action = if !self.can_double() {
+            CubeAction::NoDouble
+        } else {
+            let takes = double_take <= double_pass;
+            let double = double_take.min(double_pass);
+            if no_double >= double {
+                if !takes && no_double > double_pass {
+                    CubeAction::TooGood
+                } else {
+                    CubeAction::NoDouble
+                }
+            } else if !takes {
+                CubeAction::DoublePass
+            } else if beaver {
+                CubeAction::DoubleBeaver
+            } else {
+                CubeAction::DoubleTake
+            }
+        };
+
+        CubeDecision {
+            no_double,
+            double_take,
+            double_pass,
+            action,
+        }
+    }
+}
+
+/// Average value of a win and of a loss, in points.
+fn average_values(probs: &Probabilities) -> (f32, f32) {
+    let p = probs.win_prob();
+    let win = if p > f32::EPSILON {
+        (probs.win_n + 2.0 * probs.win_g + 3.0 * probs.win_b) / p
+    } else {
+        1.0
+    };
+    let lose = if 1.0 - p > f32::EPSILON {
+        (probs.lose_n + 2.0 * probs.lose_g + 3.0 * probs.lose_b) / (1.0 - p)
+    } else {
+        1.0
+    };
+    (win, lose)
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CubeAction {
+    NoDouble,
+    DoubleTake,
+    DoubleBeaver,
+    DoublePass,
+    TooGood,
+}
+
+/// Cubeful equities of the player on roll for each cube action.
+#[derive(PartialEq, Clone, Copy)]
+pub struct CubeDecision {
+    pub no_double: f32,
+    pub double_take: f32,
+    pub double_pass: f32,
+    pub action: CubeAction,
+}
+
+impl CubeDecision {
+    /// Equity after the recommended action, in points.
+    pub fn equity(&self) -> f32 {
+        match self.action {
+            CubeAction::NoDouble | CubeAction::TooGood => self.no_double,
+            CubeAction::DoubleTake | CubeAction::DoubleBeaver => self.double_take,
+            CubeAction::DoublePass => self.double_pass,
+        }
+    }
+}
+
+impl fmt::Debug for CubeDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "CubeDecision: {:?}; nd {:.3}; dt {:.3}; dp {:.3}",
+            self.action, self.no_double, self.double_take, self.double_pass
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cube::{CubeAction, CubeState, DEFAULT_CUBE_EFFICIENCY};
+    use crate::probabilities::Probabilities;
+
+    fn gammonless(p: f32) -> Probabilities {
+        Probabilities {
+            win_n: p,
+            win_g: 0.0,
+            win_b: 0.0,
+            lose_n: 1.0 - p,
+            lose_g: 0.0,
+            lose_b: 0.0,
+        }
+    }
+
+    #[test]
+    fn no_double() {
+        let decision = CubeState::new().decision(&gammonless(0.6), DEFAULT_CUBE_EFFICIENCY);
+        assert_eq!(decision.action, CubeAction::NoDouble);
+    }
+
+    #[test]
+    fn double_take() {
+        let decision = CubeState::new().decision(&gammonless(0.72), DEFAULT_CUBE_EFFICIENCY);
+        assert_eq!(decision.action, CubeAction::DoubleTake);
+    }
+
+    #[test]
+    fn double_pass() {
+        let decision = CubeState::new().decision(&gammonless(0.85), DEFAULT_CUBE_EFFICIENCY);
+        assert_eq!(decision.action, CubeAction::DoublePass);
+        assert_eq!(decision.equity(), 1.0);
+    }
+
+    #[test]
+    fn dead_cube_is_cubeless() {
+        let probs = gammonless(0.6);
+        let equity = CubeState::new().cubeful_equity(&probs, 0.0);
+        assert!((equity - probs.equity()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn equities_scale_with_cube_value() {
+        let probs = gammonless(0.72);
+        let cube = CubeState::new();
+        let doubled = CubeState { value: 4, ..cube };
+        let decision = cube.decision(&probs, DEFAULT_CUBE_EFFICIENCY);
+        let scaled = doubled.decision(&probs, DEFAULT_CUBE_EFFICIENCY);
+        assert_eq!(scaled.action, decision.action);
+        assert!((scaled.no_double - 4.0 * decision.no_double).abs() < 1e-5);
+        assert!((scaled.double_take - 4.0 * decision.double_take).abs() < 1e-5);
+        assert_eq!(scaled.double_pass, 4.0);
+    }
+}
@@ -0,0 +1,124 @@
+use bkgm::dice::ALL_21;
+use bkgm::dice_gen::DiceGen;
+use bkgm::Dice;
+
+/// All 36 rolls, every non double appearing twice.
+const ALL_36: [Dice; 36] = all_36();
+
+const fn all_36() -> [Dice; 36] {
+    let mut rolls = [ALL_21[0].0; 36];
+    let mut next = 0;
+    let mut i = 0;
+    while i < ALL_21.len() {
+        let (dice, n) = ALL_21[i];
+        let mut j = 0;
+        while j < n as usize {
+            rolls[next] = dice;
+            next += 1;
+            j += 1;
+        }
+        i += 1;
+    }
+    rolls
+}
+
+/// Stratifies the first `depth` rolls of a game across all 36 rolls, rotating with `trial`.
+/// Over 36 consecutive trials every first roll appears exactly once, over 1296 every
+/// combination of first and second roll. All later rolls come from the wrapped `DiceGen`.
+/// An opening roll from `roll_mixed` can't be a double, so it isn't stratified and the
+/// stratified rolls are the ones after it.
+pub struct StratifiedDice<D: DiceGen> {
+    dice_gen: D,
+    trial: usize,
+    depth: usize,
+    rolled: usize,
+}
+
+impl<D: DiceGen> StratifiedDice<D> {
+    pub fn new(dice_gen: D, trial: usize, depth: usize) -> Self {
+        assert!(depth <= 2, "only the first two rolls can be stratified");
+        Self {
+            dice_gen,
+            trial,
+            depth,
+            rolled: 0,
+        }
+    }
+
+    /// Index of the stratified roll within `ALL_36`, `None` once stratification is done.
+    fn stratum(&self) -> Option<usize> {
+        let len = ALL_36.len();
+        match self.rolled {
+            0 if self.depth > 0 => Some(self.trial % len),
+            1 if self.depth > 1 => Some((self.trial + self.trial / len) % len),
+            _ => None,
+        }
+    }
+}
+
+impl<D: DiceGen> DiceGen for StratifiedDice<D> {
+    fn roll(&mut self) -> Dice {
+        let dice = match self.stratum() {
+            Some(i) => ALL_36[i],
+            None => self.dice_gen.roll(),
+        };
+        self.rolled += 1;
+        dice
+    }
+
+    fn roll_mixed(&mut self) -> Dice {
+        self.dice_gen.roll_mixed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dice_gen::StratifiedDice;
+    use bkgm::dice::ALL_21;
+    use bkgm::dice_gen::{DiceGen, FastrandDice};
+
+    #[test]
+    fn first_roll_evenly_distributed() {
+        let k = 5;
+        let mut counts = [0; 21];
+        for trial in 0..36 * k {
+            let mut dice_gen = StratifiedDice::new(FastrandDice::new(), trial, 1);
+            let dice = dice_gen.roll();
+            let index = ALL_21.iter().position(|(d, _)| *d == dice).unwrap();
+            counts[index] += 1;
+        }
+        for ((_, n), count) in ALL_21.iter().zip(counts) {
+            assert_eq!(count, *n as usize * k);
+        }
+    }
+
+    #[test]
+    fn opening_roll_is_not_stratified() {
+        let mut counts = [0; 21];
+        for trial in 0..36 {
+            let mut dice_gen = StratifiedDice::new(FastrandDice::new(), trial, 1);
+            dice_gen.roll_mixed();
+            let dice = dice_gen.roll();
+            let index = ALL_21.iter().position(|(d, _)| *d == dice).unwrap();
+            counts[index] += 1;
+        }
+        for ((_, n), count) in ALL_21.iter().zip(counts) {
+            assert_eq!(count, *n as usize);
+        }
+    }
+
+    #[test]
+    fn second_roll_evenly_distributed() {
+        let mut counts = [0; 21];
+        for trial in 0..36 {
+            let mut dice_gen = StratifiedDice::new(FastrandDice::new(), trial, 2);
+            dice_gen.roll();
+            let dice = dice_gen.roll();
+            let index = ALL_21.iter().position(|(d, _)| *d == dice).unwrap();
+            counts[index] += 1;
+        }
+        for ((_, n), count) in ALL_21.iter().zip(counts) {
+            assert_eq!(count, *n as usize);
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use std::collections::{HashMap, HashSet};
 use std::marker::PhantomData;
 
+use crate::analysis::{self, GameRecord};
+use crate::dice_gen::StratifiedDice;
 use crate::evaluator::PartialEvaluator;
 use crate::probabilities::{Probabilities, ResultCounter};
 use bkgm::dice_gen::{DiceGen, FastrandDice};
@@ -20,6 +22,18 @@ pub fn duel<G: State>(
     evaluator1: impl PartialEvaluator<G>,
     evaluator2: impl PartialEvaluator<G>,
     rounds: usize,
+) -> Probabilities {
+    stratified_duel(state, evaluator1, evaluator2, rounds, 0)
+}
+
+/// Like `duel`, but the first `depth` rolls after the opening roll (at most two) are
+/// stratified across all 36 rolls, rotating with the round.
+pub fn stratified_duel<G: State>(
+    state: &G,
+    evaluator1: impl PartialEvaluator<G>,
+    evaluator2: impl PartialEvaluator<G>,
+    rounds: usize,
+    depth: usize,
 ) -> Probabilities {
     let duel = Duel::new(evaluator1, evaluator2);
     let mut results = ResultCounter::default();
@@ -31,7 +45,7 @@ pub fn duel<G: State>(
     for round in (0..rounds).progress() {
         let outcome = duel.single_duel(
             state,
-            &mut FastrandDice::new(),
+            &mut StratifiedDice::new(FastrandDice::new(), round, depth),
             &mut unique,
             &mut game_length,
             &mut phases,
@@ -44,6 +58,31 @@ pub fn duel<G: State>(
     results.probabilities()
 }
 
+/// Average points won by `evaluator1`, before and after subtracting the luck of the dice
+/// according to `judge`. The luck adjusted average has a much smaller variance, so fewer
+/// games are needed to tell two evaluators apart.
+pub fn luck_adjusted_duel<G: State>(
+    state: &G,
+    evaluator1: impl PartialEvaluator<G>,
+    evaluator2: impl PartialEvaluator<G>,
+    judge: &impl PartialEvaluator<G>,
+    games: usize,
+) -> (f32, f32) {
+    let duel = Duel::new(evaluator1, evaluator2);
+    let mut dice_gen = FastrandDice::new();
+    let mut points = 0.0;
+    let mut adjusted = 0.0;
+    for game in (0..games).progress() {
+        let first = game % 2 == 0;
+        let (record, won) = duel.recorded_game(state, &mut dice_gen, first);
+        let report = analysis::luck(judge, &record).expect("Recorded games are legal");
+        let luck = if first { report.net() } else { -report.net() };
+        points += won;
+        adjusted += won - luck;
+    }
+    (points / games as f32, adjusted / games as f32)
+}
+
 /// Let two `PartialEvaluator`s duel each other. A bit quick and dirty.
 impl<T: PartialEvaluator<G>, U: PartialEvaluator<G>, G: State> Duel<T, U, G> {
     #[allow(clippy::new_without_default)]
@@ -55,7 +94,40 @@ impl<T: PartialEvaluator<G>, U: PartialEvaluator<G>, G: State> Duel<T, U, G> {
         }
     }
 
-    // pub fn
+    /// Plays a single game, `evaluator1` moving first if `first`.
+    /// Returns the record of the game and the points won by `evaluator1`.
+    pub fn recorded_game<V: DiceGen>(
+        &self,
+        state: &G,
+        dice_gen: &mut V,
+        first: bool,
+    ) -> (GameRecord<G>, f32) {
+        let mut record = GameRecord::new(*state).with_opening_roll();
+        let mut pos = *state;
+        let mut dice = dice_gen.roll_mixed();
+        loop {
+            let evaluator1_on_roll = (record.moves.len() % 2 == 0) == first;
+            match pos.game_state() {
+                Ongoing => {
+                    pos = if evaluator1_on_roll {
+                        self.evaluator1.best_position(&pos, &dice)
+                    } else {
+                        self.evaluator2.best_position(&pos, &dice)
+                    };
+                    record.push(dice, pos);
+                    dice = dice_gen.roll();
+                }
+                GameOver(result) => {
+                    let points = if evaluator1_on_roll {
+                        result.value()
+                    } else {
+                        -result.value()
+                    };
+                    return (record, points);
+                }
+            }
+        }
+    }
 
     /// The two `PartialEvaluator`s will play twice each against each other.
     /// Either `PartialEvaluator` will start once and play with the same dice as vice versa.
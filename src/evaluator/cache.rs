@@ -0,0 +1,243 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::probabilities::Probabilities;
+use bkgm::State;
+
+use super::{Evaluator, PartialEvaluator};
+
+/// Number of lookups that were found in an `EvalCache` and that were not.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f32 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f32 / total as f32,
+        }
+    }
+}
+
+/// Thread safe evaluation cache with a fixed number of slots, keyed on position and search depth.
+/// Every key maps to a single slot, a new entry replaces whatever was stored there before.
+/// Wrap it in an `Arc` to share it between evaluators and threads.
+pub struct EvalCache<G: State, V: Copy> {
+    slots: Vec<Mutex<Option<(G, usize, V)>>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl<G: State, V: Copy> EvalCache<G, V> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be greater than 0");
+        Self {
+            slots: (0..capacity).map(|_| Mutex::new(None)).collect(),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    fn slot(&self, pos: &G, depth: usize) -> &Mutex<Option<(G, usize, V)>> {
+        let mut hasher = DefaultHasher::new();
+        pos.hash(&mut hasher);
+        depth.hash(&mut hasher);
+        &self.slots[(hasher.finish() % self.slots.len() as u64) as usize]
+    }
+
+    pub fn get(&self, pos: &G, depth: usize) -> Option<V> {
+        let value = match *self.slot(pos, depth).lock().unwrap() {
+            Some((cached, cached_depth, value)) if cached == *pos && cached_depth == depth => {
+                Some(value)
+            }
+            _ => None,
+        };
+        match value {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        value
+    }
+
+    pub fn insert(&self, pos: &G, depth: usize, value: V) {
+        *self.slot(pos, depth).lock().unwrap() = Some((*pos, depth, value));
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Removes all entries and resets the statistics.
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            *slot.lock().unwrap() = None;
+        }
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Caches the evaluations of any evaluator, e.g. the leaves of a rollout or a `PlyEvaluator`.
+pub struct CachedEvaluator<G: State, E: PartialEvaluator<G>> {
+    phantom: PhantomData<G>,
+    evaluator: E,
+    values: Arc<EvalCache<G, f32>>,
+    probabilities: Option<Arc<EvalCache<G, Probabilities>>>,
+}
+
+impl<G: State, E: PartialEvaluator<G>> PartialEvaluator<G> for CachedEvaluator<G, E> {
+    fn try_eval(&self, pos: &G) -> f32 {
+        if let Some(value) = self.values.get(pos, 0) {
+            return value;
+        }
+        let value = self.evaluator.try_eval(pos);
+        self.values.insert(pos, 0, value);
+        value
+    }
+
+    /// Only the positions that aren't cached are passed on, as a single batch.
+    fn try_eval_batch(&self, positions: &[G]) -> Vec<f32> {
+        cached_batch(&self.values, positions, |missing| {
+            self.evaluator.try_eval_batch(missing)
+        })
+    }
+}
+
+impl<G: State, E: Evaluator<G>> Evaluator<G> for CachedEvaluator<G, E> {
+    fn eval(&self, pos: &G) -> Probabilities {
+        let Some(cache) = &self.probabilities else {
+            return self.evaluator.eval(pos);
+        };
+        if let Some(probs) = cache.get(pos, 0) {
+            return probs;
+        }
+        let probs = self.evaluator.eval(pos);
+        cache.insert(pos, 0, probs);
+        probs
+    }
+
+    fn eval_batch(&self, positions: &[G]) -> Vec<Probabilities> {
+        let Some(cache) = &self.probabilities else {
+            return self.evaluator.eval_batch(positions);
+        };
+        cached_batch(cache, positions, |missing| {
+            self.evaluator.eval_batch(missing)
+        })
+    }
+}
+
+/// Looks up all `positions` in `cache`, evaluating the missing ones with a single call to `evaluate`
+/// and storing the results.
+pub(super) fn cached_batch<G: State, V: Copy>(
+    cache: &EvalCache<G, V>,
+    positions: &[G],
+    evaluate: impl FnOnce(&[G]) -> Vec<V>,
+) -> Vec<V> {
+    let mut values: Vec<Option<V>> = positions.iter().map(|pos| cache.get(pos, 0)).collect();
+    let missing: Vec<G> = positions
+        .iter()
+        .zip(&values)
+        .filter(|(_, value)| value.is_none())
+        .map(|(pos, _)| *pos)
+        .collect();
+    if !missing.is_empty() {
+        let mut evaluated = evaluate(&missing).into_iter();
+        for (pos, value) in positions.iter().zip(values.iter_mut()) {
+            if value.is_none() {
+                let evaluation = evaluated.next().unwrap();
+                cache.insert(pos, 0, evaluation);
+                *value = Some(evaluation);
+            }
+        }
+    }
+    values.into_iter().map(Option::unwrap).collect()
+}
+
+impl<G: State, E: PartialEvaluator<G>> CachedEvaluator<G, E> {
+    pub fn new(evaluator: E, values: Arc<EvalCache<G, f32>>) -> Self {
+        Self {
+            phantom: PhantomData,
+            evaluator,
+            values,
+            probabilities: None,
+        }
+    }
+
+    /// Also caches the probabilities returned by `eval`.
+    pub fn with_probabilities(mut self, probabilities: Arc<EvalCache<G, Probabilities>>) -> Self {
+        self.probabilities = Some(probabilities);
+        self
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.values.stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use crate::evaluator::{CachedEvaluator, EvalCache, PartialEvaluator};
+    use bkgm::{Dice, State, HYPERGAMMON};
+
+    /// Counts the positions passed to `try_eval_batch`.
+    struct BatchCounter(AtomicUsize);
+
+    impl<G: State> PartialEvaluator<G> for BatchCounter {
+        fn try_eval(&self, pos: &G) -> f32 {
+            pos.pip(24) as f32
+        }
+
+        fn try_eval_batch(&self, positions: &[G]) -> Vec<f32> {
+            self.0.fetch_add(positions.len(), Ordering::Relaxed);
+            positions.iter().map(|pos| self.try_eval(pos)).collect()
+        }
+    }
+
+    #[test]
+    fn hits_and_misses() {
+        let cache = EvalCache::new(16);
+        assert_eq!(cache.get(&HYPERGAMMON, 1), None);
+        cache.insert(&HYPERGAMMON, 1, 0.25);
+        assert_eq!(cache.get(&HYPERGAMMON, 1), Some(0.25));
+        assert_eq!(cache.get(&HYPERGAMMON, 2), None);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 2));
+        assert!((stats.hit_rate() - 1.0 / 3.0).abs() < 1e-6);
+
+        cache.clear();
+        assert_eq!(cache.get(&HYPERGAMMON, 1), None);
+    }
+
+    #[test]
+    fn batches_only_misses() {
+        let cached = CachedEvaluator::new(
+            BatchCounter(AtomicUsize::new(0)),
+            Arc::new(EvalCache::new(1 << 12)),
+        );
+        let positions = HYPERGAMMON.possible_positions(&Dice::new(3, 1));
+        let expected: Vec<f32> = positions.iter().map(|pos| pos.pip(24) as f32).collect();
+
+        assert_eq!(cached.try_eval_batch(&positions[..1]), expected[..1]);
+        assert_eq!(cached.try_eval_batch(&positions), expected);
+        assert_eq!(cached.evaluator.0.load(Ordering::Relaxed), positions.len());
+        assert_eq!(cached.try_eval_batch(&positions), expected);
+        assert_eq!(cached.evaluator.0.load(Ordering::Relaxed), positions.len());
+    }
+}
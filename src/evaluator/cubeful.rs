@@ -0,0 +1,56 @@
+use std::marker::PhantomData;
+
+use crate::cube::{CubeDecision, CubeState, DEFAULT_CUBE_EFFICIENCY};
+use crate::probabilities::Probabilities;
+use bkgm::State;
+
+use super::{Evaluator, PartialEvaluator};
+
+/// Turns the cubeless evaluation of any `Evaluator` into cubeful money game equities.
+pub struct CubefulEvaluator<G: State, E: Evaluator<G>> {
+    phantom: PhantomData<G>,
+    evaluator: E,
+    cube_efficiency: f32,
+}
+
+impl<G: State, E: Evaluator<G>> PartialEvaluator<G> for CubefulEvaluator<G, E> {
+    fn try_eval(&self, pos: &G) -> f32 {
+        self.evaluator.try_eval(pos)
+    }
+
+    fn best_position(&self, pos: &G, dice: &bkgm::Dice) -> G {
+        self.evaluator.best_position(pos, dice)
+    }
+}
+
+impl<G: State, E: Evaluator<G>> Evaluator<G> for CubefulEvaluator<G, E> {
+    fn eval(&self, pos: &G) -> Probabilities {
+        self.evaluator.eval(pos)
+    }
+}
+
+impl<G: State, E: Evaluator<G>> CubefulEvaluator<G, E> {
+    pub fn new(evaluator: E) -> Self {
+        Self::with_cube_efficiency(evaluator, DEFAULT_CUBE_EFFICIENCY)
+    }
+
+    /// `cube_efficiency` ranges from 0.0 (dead cube) to 1.0 (fully live cube).
+    pub fn with_cube_efficiency(evaluator: E, cube_efficiency: f32) -> Self {
+        Self {
+            phantom: PhantomData,
+            evaluator,
+            cube_efficiency,
+        }
+    }
+
+    /// Cubeful equity of the player on roll in points, so scaled by the cube value.
+    pub fn cubeful_equity(&self, pos: &G, cube: &CubeState) -> f32 {
+        cube.cubeful_equity(&self.evaluator.eval(pos), self.cube_efficiency)
+    }
+
+    /// No double, double/take and double/pass equities of the player on roll,
+    /// together with the recommended cube action.
+    pub fn cube_decision(&self, pos: &G, cube: &CubeState) -> CubeDecision {
+        cube.decision(&self.evaluator.eval(pos), self.cube_efficiency)
+    }
+}
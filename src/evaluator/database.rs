@@ -0,0 +1,415 @@
+use crate::probabilities::Probabilities;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Identifies rassay database files.
+pub const MAGIC: [u8; 4] = *b"RSDB";
+pub const VERSION: u16 = 1;
+/// The header is padded, so fields can be added without moving the values.
+pub const HEADER_SIZE: usize = 64;
+
+/// Value stored for every position of a database.
+pub trait DatabaseValue: Copy {
+    /// Size in bytes
+    const SIZE: usize;
+    /// Stored in the header, so equities aren't read as probabilities or vice versa.
+    const KIND: u8;
+
+    fn from_bytes(bytes: &[u8]) -> Self;
+
+    fn to_bytes(&self) -> Vec<u8>;
+
+    fn equity(&self) -> f32;
+}
+
+/// Stored as the cubeless equity.
+impl DatabaseValue for f32 {
+    const SIZE: usize = 4;
+    const KIND: u8 = 0;
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        f32::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.to_le_bytes().to_vec()
+    }
+
+    fn equity(&self) -> f32 {
+        *self
+    }
+}
+
+/// Stored as the five cumulative gnubg probabilities: win, win gammon, win backgammon,
+/// lose gammon, lose backgammon.
+impl DatabaseValue for Probabilities {
+    const SIZE: usize = 20;
+    const KIND: u8 = 1;
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let wgbgb: [f32; 5] = std::array::from_fn(|i| {
+            f32::from_le_bytes(bytes[4 * i..4 * i + 4].try_into().unwrap())
+        });
+        Probabilities {
+            win_n: wgbgb[0] - wgbgb[1],
+            win_g: wgbgb[1] - wgbgb[2],
+            win_b: wgbgb[2],
+            lose_n: 1.0 - wgbgb[0] - wgbgb[3],
+            lose_g: wgbgb[3] - wgbgb[4],
+            lose_b: wgbgb[4],
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        [
+            self.win_n + self.win_g + self.win_b,
+            self.win_g + self.win_b,
+            self.win_b,
+            self.lose_g + self.lose_b,
+            self.lose_b,
+        ]
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+    }
+
+    fn equity(&self) -> f32 {
+        Probabilities::equity(self)
+    }
+}
+
+/// Variants of the games stored in databases, so databases of different rules with the same
+/// number of checkers aren't mixed up. Files written before the variant was stored have the
+/// zeroed padding, which reads as hypergammon.
+pub const VARIANT_HYPERGAMMON: u8 = 0;
+
+fn variant_name(variant: u8) -> &'static str {
+    match variant {
+        VARIANT_HYPERGAMMON => "hypergammon",
+        _ => "an unknown variant",
+    }
+}
+
+fn kind_name(kind: u8) -> &'static str {
+    match kind {
+        0 => "equities",
+        1 => "probabilities",
+        _ => "unknown values",
+    }
+}
+
+#[derive(Debug)]
+pub enum DatabaseError {
+    Io(io::Error),
+    /// Not a rassay database, e.g. a headerless file from before versioning.
+    BadMagic,
+    UnsupportedVersion(u16),
+    WrongVariant {
+        expected: u8,
+        found: u8,
+    },
+    WrongKind {
+        expected: u8,
+        found: u8,
+    },
+    WrongCheckers {
+        expected: u8,
+        found: u8,
+    },
+    /// Number of positions, according to the header or the length of the file.
+    WrongSize {
+        expected: usize,
+        found: usize,
+    },
+    ChecksumMismatch {
+        expected: u64,
+        found: u64,
+    },
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DatabaseError::Io(err) => write!(f, "{}", err),
+            DatabaseError::BadMagic => {
+                write!(f, "not a database file, convert raw files with convertdb")
+            }
+            DatabaseError::UnsupportedVersion(version) => write!(
+                f,
+                "database version {} is not supported, expected {}",
+                version, VERSION
+            ),
+            DatabaseError::WrongVariant { expected, found } => write!(
+                f,
+                "database is for {}, expected {}",
+                variant_name(*found),
+                variant_name(*expected)
+            ),
+            DatabaseError::WrongKind { expected, found } => write!(
+                f,
+                "database holds {}, expected {}",
+                kind_name(*found),
+                kind_name(*expected)
+            ),
+            DatabaseError::WrongCheckers { expected, found } => write!(
+                f,
+                "database is for {} checkers, expected {}",
+                found, expected
+            ),
+            DatabaseError::WrongSize { expected, found } => {
+                write!(f, "database has {} positions, expected {}", found, expected)
+            }
+            DatabaseError::ChecksumMismatch { expected, found } => write!(
+                f,
+                "database is corrupt, checksum {:016x} instead of {:016x}",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+impl From<io::Error> for DatabaseError {
+    fn from(err: io::Error) -> Self {
+        DatabaseError::Io(err)
+    }
+}
+
+/// How a database was generated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metadata {
+    pub iterations: u32,
+    pub discount: f32,
+    /// Largest change of a value in the last iteration.
+    pub max_error: f32,
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Self {
+            iterations: 0,
+            discount: 1.0,
+            max_error: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DatabaseHeader {
+    pub version: u16,
+    pub variant: u8,
+    pub kind: u8,
+    pub checkers: u8,
+    pub positions: u64,
+    pub metadata: Metadata,
+    /// FNV-1a hash of the values.
+    pub checksum: u64,
+}
+
+impl DatabaseHeader {
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0; HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4..6].copy_from_slice(&self.version.to_le_bytes());
+        bytes[6] = self.kind;
+        bytes[7] = self.checkers;
+        bytes[8..16].copy_from_slice(&self.positions.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.metadata.iterations.to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.metadata.discount.to_le_bytes());
+        bytes[24..28].copy_from_slice(&self.metadata.max_error.to_le_bytes());
+        bytes[28..36].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes[36] = self.variant;
+        bytes
+    }
+
+    /// Checks the magic number and version only, the caller checks the rest.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DatabaseError> {
+        if bytes.len() < HEADER_SIZE || bytes[0..4] != MAGIC {
+            return Err(DatabaseError::BadMagic);
+        }
+        let version = u16::from_le_bytes(bytes[4..6].try_into().unwrap());
+        if version != VERSION {
+            return Err(DatabaseError::UnsupportedVersion(version));
+        }
+        Ok(Self {
+            version,
+            variant: bytes[36],
+            kind: bytes[6],
+            checkers: bytes[7],
+            positions: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+            metadata: Metadata {
+                iterations: u32::from_le_bytes(bytes[16..20].try_into().unwrap()),
+                discount: f32::from_le_bytes(bytes[20..24].try_into().unwrap()),
+                max_error: f32::from_le_bytes(bytes[24..28].try_into().unwrap()),
+            },
+            checksum: u64::from_le_bytes(bytes[28..36].try_into().unwrap()),
+        })
+    }
+
+    /// Checks that a file of `len` bytes with this header holds `positions` values of type
+    /// `V` for `variant` with `checkers` checkers.
+    pub fn validate<V: DatabaseValue>(
+        &self,
+        variant: u8,
+        checkers: u8,
+        positions: usize,
+        len: usize,
+    ) -> Result<(), DatabaseError> {
+        if self.variant != variant {
+            return Err(DatabaseError::WrongVariant {
+                expected: variant,
+                found: self.variant,
+            });
+        }
+        if self.kind != V::KIND {
+            return Err(DatabaseError::WrongKind {
+                expected: V::KIND,
+                found: self.kind,
+            });
+        }
+        if self.checkers != checkers {
+            return Err(DatabaseError::WrongCheckers {
+                expected: checkers,
+                found: self.checkers,
+            });
+        }
+        if self.positions != positions as u64 {
+            return Err(DatabaseError::WrongSize {
+                expected: positions,
+                found: self.positions as usize,
+            });
+        }
+        if len != HEADER_SIZE + positions * V::SIZE {
+            return Err(DatabaseError::WrongSize {
+                expected: positions,
+                found: len.saturating_sub(HEADER_SIZE) / V::SIZE,
+            });
+        }
+        Ok(())
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+fn extend_checksum(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// 64-bit FNV-1a.
+pub fn checksum(bytes: &[u8]) -> u64 {
+    extend_checksum(FNV_OFFSET, bytes)
+}
+
+/// Writes a database of `values` for `variant` with `checkers` checkers with a header.
+/// The file is written next to the target and renamed over it once synced, so an interrupted
+/// write never leaves a truncated database behind.
+pub fn write_database<V: DatabaseValue>(
+    file_path: impl AsRef<Path>,
+    variant: u8,
+    checkers: u8,
+    values: &[V],
+    metadata: Metadata,
+) -> io::Result<()> {
+    let checksum = values.iter().fold(FNV_OFFSET, |hash, value| {
+        extend_checksum(hash, &value.to_bytes())
+    });
+    let header = DatabaseHeader {
+        version: VERSION,
+        variant,
+        kind: V::KIND,
+        checkers,
+        positions: values.len() as u64,
+        metadata,
+        checksum,
+    };
+    let file_path = file_path.as_ref();
+    let temp_path = temp_path(file_path);
+    let written = write_file(&temp_path, &header, values);
+    if written.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+        return written;
+    }
+    std::fs::rename(&temp_path, file_path)
+}
+
+/// Sibling of `file_path`, so renaming it over `file_path` stays on the same file system.
+fn temp_path(file_path: &Path) -> PathBuf {
+    let mut name = file_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    file_path.with_file_name(name)
+}
+
+fn write_file<V: DatabaseValue>(
+    file_path: &Path,
+    header: &DatabaseHeader,
+    values: &[V],
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(file_path)?);
+    writer.write_all(&header.to_bytes())?;
+    for value in values {
+        writer.write_all(&value.to_bytes())?;
+    }
+    writer.into_inner()?.sync_all()
+}
+
+/// Reads a headerless file of values, as written before databases were versioned.
+pub fn read_raw<V: DatabaseValue>(
+    file_path: impl AsRef<Path>,
+    positions: usize,
+) -> Result<Vec<V>, DatabaseError> {
+    let bytes = std::fs::read(file_path)?;
+    if bytes.len() != positions * V::SIZE {
+        return Err(DatabaseError::WrongSize {
+            expected: positions,
+            found: bytes.len() / V::SIZE,
+        });
+    }
+    Ok(bytes.chunks_exact(V::SIZE).map(V::from_bytes).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::database::{DatabaseHeader, Metadata, VARIANT_HYPERGAMMON, VERSION};
+    use crate::evaluator::DatabaseError;
+    use crate::probabilities::Probabilities;
+
+    #[test]
+    fn header_round_trip() {
+        let header = DatabaseHeader {
+            version: VERSION,
+            variant: VARIANT_HYPERGAMMON,
+            kind: 1,
+            checkers: 3,
+            positions: 123,
+            metadata: Metadata {
+                iterations: 40,
+                discount: 0.99,
+                max_error: 1e-5,
+            },
+            checksum: 42,
+        };
+        let bytes = header.to_bytes();
+        assert_eq!(DatabaseHeader::from_bytes(&bytes).unwrap(), header);
+        assert!(header
+            .validate::<Probabilities>(VARIANT_HYPERGAMMON, 3, 123, bytes.len() + 123 * 20)
+            .is_ok());
+        assert!(matches!(
+            header.validate::<f32>(VARIANT_HYPERGAMMON, 3, 123, bytes.len() + 123 * 4),
+            Err(DatabaseError::WrongKind { .. })
+        ));
+        assert!(matches!(
+            header.validate::<Probabilities>(1, 3, 123, bytes.len() + 123 * 20),
+            Err(DatabaseError::WrongVariant { .. })
+        ));
+        assert!(matches!(
+            DatabaseHeader::from_bytes(&[0; 64]),
+            Err(DatabaseError::BadMagic)
+        ));
+    }
+}
@@ -0,0 +1,136 @@
+use std::marker::PhantomData;
+
+use crate::probabilities::Probabilities;
+use bkgm::State;
+
+use super::{Evaluator, PartialEvaluator};
+
+/// Weighted average of several evaluators, e.g. checkpoints of the same training run.
+pub struct EnsembleEvaluator<G: State, E: PartialEvaluator<G>> {
+    phantom: PhantomData<G>,
+    members: Vec<E>,
+    /// Normalised to sum up to 1.0.
+    weights: Vec<f32>,
+}
+
+impl<G: State, E: PartialEvaluator<G>> PartialEvaluator<G> for EnsembleEvaluator<G, E> {
+    fn try_eval(&self, pos: &G) -> f32 {
+        self.try_eval_with_disagreement(pos).0
+    }
+
+    fn try_eval_batch(&self, positions: &[G]) -> Vec<f32> {
+        let mut values = vec![0.0; positions.len()];
+        for (member, weight) in self.members.iter().zip(&self.weights) {
+            for (value, member_value) in values.iter_mut().zip(member.try_eval_batch(positions)) {
+                *value += weight * member_value;
+            }
+        }
+        values
+    }
+}
+
+impl<G: State, E: Evaluator<G>> Evaluator<G> for EnsembleEvaluator<G, E> {
+    fn eval(&self, pos: &G) -> Probabilities {
+        self.members
+            .iter()
+            .zip(&self.weights)
+            .fold(Probabilities::empty(), |probs, (member, weight)| {
+                probs + member.eval(pos) * *weight
+            })
+    }
+
+    fn eval_batch(&self, positions: &[G]) -> Vec<Probabilities> {
+        let mut probs = vec![Probabilities::empty(); positions.len()];
+        for (member, weight) in self.members.iter().zip(&self.weights) {
+            for (probs, member_probs) in probs.iter_mut().zip(member.eval_batch(positions)) {
+                *probs = *probs + member_probs * *weight;
+            }
+        }
+        probs
+    }
+}
+
+impl<G: State, E: PartialEvaluator<G>> EnsembleEvaluator<G, E> {
+    /// Ensemble with equal weights.
+    pub fn new(members: Vec<E>) -> Self {
+        assert!(!members.is_empty(), "ensemble needs at least one member");
+        let weights = vec![1.0 / members.len() as f32; members.len()];
+        Self {
+            phantom: PhantomData,
+            members,
+            weights,
+        }
+    }
+
+    /// Weights of the members in the same order, normalised to sum up to 1.0.
+    pub fn with_weights(mut self, weights: Vec<f32>) -> Self {
+        assert_eq!(
+            weights.len(),
+            self.members.len(),
+            "one weight per member required"
+        );
+        assert!(
+            weights.iter().all(|weight| *weight >= 0.0),
+            "weights can't be negative"
+        );
+        let sum: f32 = weights.iter().sum();
+        assert!(sum > 0.0, "weights can't all be zero");
+        self.weights = weights.iter().map(|weight| weight / sum).collect();
+        self
+    }
+
+    pub fn members(&self) -> &[E] {
+        &self.members
+    }
+
+    pub fn weights(&self) -> &[f32] {
+        &self.weights
+    }
+
+    /// Weighted mean and weighted standard deviation of the members' evaluations.
+    /// A large standard deviation means the members disagree and the evaluation is uncertain.
+    pub fn try_eval_with_disagreement(&self, pos: &G) -> (f32, f32) {
+        let values: Vec<f32> = self
+            .members
+            .iter()
+            .map(|member| member.try_eval(pos))
+            .collect();
+        let mean: f32 = values.iter().zip(&self.weights).map(|(v, w)| v * w).sum();
+        let variance: f32 = values
+            .iter()
+            .zip(&self.weights)
+            .map(|(v, w)| w * (v - mean).powi(2))
+            .sum();
+        (mean, variance.sqrt())
+    }
+
+    /// Weighted standard deviation of the members' evaluations of `pos`.
+    pub fn disagreement(&self, pos: &G) -> f32 {
+        self.try_eval_with_disagreement(pos).1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{EnsembleEvaluator, PartialEvaluator};
+    use bkgm::{State, HYPERGAMMON};
+
+    struct Constant(f32);
+
+    impl<G: State> PartialEvaluator<G> for Constant {
+        fn try_eval(&self, _pos: &G) -> f32 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn weighted_mean_and_disagreement() {
+        let ensemble = EnsembleEvaluator::new(vec![Constant(0.2), Constant(0.6)]);
+        let (mean, std_dev) = ensemble.try_eval_with_disagreement(&HYPERGAMMON);
+        assert!((mean - 0.4).abs() < 1e-6);
+        assert!((std_dev - 0.2).abs() < 1e-6);
+
+        let ensemble = ensemble.with_weights(vec![3.0, 1.0]);
+        assert!((ensemble.try_eval(&HYPERGAMMON) - 0.3).abs() < 1e-6);
+    }
+}
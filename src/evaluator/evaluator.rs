@@ -9,10 +9,18 @@ pub trait PartialEvaluator<G: State>: Sized {
     /// Examples of such strategies are a rollout or 1-ply inference of a neural net.
     fn try_eval(&self, pos: &G) -> f32;
 
+    /// Evaluates several positions at once, which is much faster for neural nets.
+    /// Defaults to evaluating the positions one by one.
+    fn try_eval_batch(&self, positions: &[G]) -> Vec<f32> {
+        positions.iter().map(|pos| self.try_eval(pos)).collect()
+    }
+
     fn best_position(&self, pos: &G, dice: &Dice) -> G {
-        *pos.possible_positions(dice)
+        let positions = pos.possible_positions(dice);
+        let values = self.try_eval_batch(&positions);
+        *positions
             .iter()
-            .map(|pos| (pos, self.try_eval(&pos)))
+            .zip(values)
             .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
             .unwrap()
             .0
@@ -24,6 +32,36 @@ pub trait Evaluator<G: State>: PartialEvaluator<G> + Sized {
     /// Implementing types will calculate the probabilities with different strategies.
     /// Examples of such strategies are a rollout or 1-ply inference of a neural net.
     fn eval(&self, pos: &G) -> Probabilities;
+
+    /// Evaluates several positions at once, defaults to evaluating them one by one.
+    fn eval_batch(&self, positions: &[G]) -> Vec<Probabilities> {
+        positions.iter().map(|pos| self.eval(pos)).collect()
+    }
+}
+
+/// Lets a borrowed evaluator stand in wherever an evaluator is taken by value.
+impl<G: State, E: PartialEvaluator<G>> PartialEvaluator<G> for &E {
+    fn try_eval(&self, pos: &G) -> f32 {
+        (**self).try_eval(pos)
+    }
+
+    fn try_eval_batch(&self, positions: &[G]) -> Vec<f32> {
+        (**self).try_eval_batch(positions)
+    }
+
+    fn best_position(&self, pos: &G, dice: &Dice) -> G {
+        (**self).best_position(pos, dice)
+    }
+}
+
+impl<G: State, E: Evaluator<G>> Evaluator<G> for &E {
+    fn eval(&self, pos: &G) -> Probabilities {
+        (**self).eval(pos)
+    }
+
+    fn eval_batch(&self, positions: &[G]) -> Vec<Probabilities> {
+        (**self).eval_batch(positions)
+    }
 }
 
 pub trait ONNEvaluator<B: Backend, G: State>: Evaluator<G> + Sized {
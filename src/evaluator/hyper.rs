@@ -1,68 +1,240 @@
 use crate::evaluator::Evaluator;
 use crate::probabilities::Probabilities;
 use bkgm::{utils::mcomb, State};
+use memmap2::Mmap;
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io;
+use std::marker::PhantomData;
 use std::path::Path;
+use std::sync::Arc;
 
+use super::database::{
+    checksum, write_database, DatabaseError, DatabaseHeader, DatabaseValue, Metadata, HEADER_SIZE,
+    VARIANT_HYPERGAMMON,
+};
 use super::PartialEvaluator;
 
-const POSSIBLE: usize = mcomb(26, 3 as usize).pow(2);
-
+/// Perfect evaluations of every position of a variant with `N` checkers per side,
+/// indexed by `dbhash`, so it evaluates any state with `N` checkers per side.
+/// Databases of probabilities are full `Evaluator`s, databases of equities only `PartialEvaluator`s.
+/// The file is memory mapped and values are decoded when looked up, so loading is instant
+/// and processes using the same file share one copy in the page cache. Clones share the map.
 #[derive(Clone)]
-pub struct HyperEvaluator {
-    probs: Vec<Probabilities>,
+pub struct HyperEvaluator<const N: u8, V: DatabaseValue = Probabilities> {
+    phantom: PhantomData<V>,
+    bytes: Arc<Mmap>,
 }
 
-impl<G: State> PartialEvaluator<G> for HyperEvaluator {
+impl<G: State, const N: u8, V: DatabaseValue> PartialEvaluator<G> for HyperEvaluator<N, V> {
     fn try_eval(&self, pos: &G) -> f32 {
-        let probs = self.eval(pos);
-        probs.equity()
+        self.value(Self::hash(pos)).equity()
     }
 }
 
-impl<G: State> Evaluator<G> for HyperEvaluator {
-    fn eval(&self, position: &G) -> Probabilities {
-        self.probs[position.dbhash()]
+impl<G: State, const N: u8> Evaluator<G> for HyperEvaluator<N, Probabilities> {
+    fn eval(&self, pos: &G) -> Probabilities {
+        self.value(Self::hash(pos))
     }
 }
 
-impl HyperEvaluator {
-    pub fn new() -> Option<Self> {
+impl HyperEvaluator<3> {
+    pub fn new() -> Result<Self, DatabaseError> {
         Self::from_file("data/hyper.db")
     }
+}
 
-    pub fn from_file(file_path: impl AsRef<Path>) -> Option<Self> {
-        let file = File::open(file_path).expect("File not found");
+impl<const N: u8, V: DatabaseValue> HyperEvaluator<N, V> {
+    /// Number of positions, reachable or not.
+    pub fn possible() -> usize {
+        mcomb(26, N as usize).pow(2)
+    }
 
-        let mut reader = BufReader::new(file);
+    /// Fails if the file isn't a database of values of type `V` for `N` checkers.
+    /// The checksum isn't checked, as that means reading the whole file; see `verify`.
+    pub fn from_file(file_path: impl AsRef<Path>) -> Result<Self, DatabaseError> {
+        let file = File::open(file_path)?;
+        // SAFETY: databases are never modified once written
+        let bytes = unsafe { Mmap::map(&file) }?;
+        let header = DatabaseHeader::from_bytes(&bytes)?;
+        header.validate::<V>(VARIANT_HYPERGAMMON, N, Self::possible(), bytes.len())?;
+        Ok(Self {
+            phantom: PhantomData,
+            bytes: Arc::new(bytes),
+        })
+    }
 
-        let mut buffer = [0u8; 20];
-        let mut probs = Vec::new();
+    /// Like `from_file`, but also checks the checksum.
+    pub fn from_file_verified(file_path: impl AsRef<Path>) -> Result<Self, DatabaseError> {
+        let db = Self::from_file(file_path)?;
+        db.verify()?;
+        Ok(db)
+    }
 
-        while reader.read_exact(&mut buffer).is_ok() {
-            let wgbgb = [
-                f32::from_le_bytes(buffer[0..4].try_into().unwrap()),
-                f32::from_le_bytes(buffer[4..8].try_into().unwrap()),
-                f32::from_le_bytes(buffer[8..12].try_into().unwrap()),
-                f32::from_le_bytes(buffer[12..16].try_into().unwrap()),
-                f32::from_le_bytes(buffer[16..20].try_into().unwrap()),
-            ];
+    pub fn header(&self) -> DatabaseHeader {
+        DatabaseHeader::from_bytes(&self.bytes).expect("Validated when loaded")
+    }
 
-            probs.push(Probabilities {
-                win_n: wgbgb[0] - wgbgb[1],
-                win_g: wgbgb[1] - wgbgb[2],
-                win_b: wgbgb[2],
-                lose_n: 1.0 - wgbgb[0] - wgbgb[3],
-                lose_g: wgbgb[3] - wgbgb[4],
-                lose_b: wgbgb[4],
-            });
+    /// Compares the checksum of the values with the one in the header.
+    pub fn verify(&self) -> Result<(), DatabaseError> {
+        let expected = self.header().checksum;
+        let found = checksum(&self.bytes[HEADER_SIZE..]);
+        match expected == found {
+            true => Ok(()),
+            false => Err(DatabaseError::ChecksumMismatch { expected, found }),
         }
+    }
+
+    fn hash<G: State>(pos: &G) -> usize {
+        debug_assert_eq!(
+            G::NUM_CHECKERS,
+            N,
+            "database is for another number of checkers"
+        );
+        pos.dbhash()
+    }
+
+    /// Value of the position with `dbhash` `hash`.
+    pub fn value(&self, hash: usize) -> V {
+        let start = HEADER_SIZE + hash * V::SIZE;
+        V::from_bytes(&self.bytes[start..start + V::SIZE])
+    }
 
-        if probs.len() == POSSIBLE {
-            Some(Self { probs })
-        } else {
-            None
+    /// Writes a database of one value for every position, in the format `from_file` reads.
+    pub fn write(file_path: impl AsRef<Path>, values: &[V], metadata: Metadata) -> io::Result<()> {
+        if values.len() != Self::possible() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} values for {} positions", values.len(), Self::possible()),
+            ));
         }
+        write_database(file_path, VARIANT_HYPERGAMMON, N, values, metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::database::{read_raw, HEADER_SIZE};
+    use crate::evaluator::{DatabaseError, HyperEvaluator, Metadata, SubHyperEvaluator};
+    use std::path::PathBuf;
+
+    #[test]
+    fn sizes_are_validated() {
+        let equities: Vec<f32> = (0..SubHyperEvaluator::<2>::possible())
+            .map(|i| i as f32)
+            .collect();
+        let path = std::env::temp_dir().join("rassay-hyper2-equity.db");
+        let metadata = Metadata {
+            iterations: 12,
+            ..Metadata::default()
+        };
+        SubHyperEvaluator::<2>::write(&path, &equities, metadata).unwrap();
+
+        let db = SubHyperEvaluator::<2>::from_file_verified(&path).unwrap();
+        assert_eq!(db.value(7), 7.0);
+        assert_eq!(db.header().metadata, metadata);
+        assert!(matches!(
+            SubHyperEvaluator::<3>::from_file(&path),
+            Err(DatabaseError::WrongCheckers { .. })
+        ));
+        assert!(matches!(
+            HyperEvaluator::<2>::from_file(&path),
+            Err(DatabaseError::WrongKind { .. })
+        ));
+        assert!(SubHyperEvaluator::<2>::write(&path, &[0.0], metadata).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// Writes a database of two checkers to the temporary file `name` and returns its bytes.
+    fn write_temp(name: &str) -> (PathBuf, Vec<u8>) {
+        let equities: Vec<f32> = (0..SubHyperEvaluator::<2>::possible())
+            .map(|i| i as f32)
+            .collect();
+        let path = std::env::temp_dir().join(name);
+        SubHyperEvaluator::<2>::write(&path, &equities, Metadata::default()).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        (path, bytes)
+    }
+
+    #[test]
+    fn write_leaves_no_temporary_file() {
+        let (path, _) = write_temp("rassay-hyper2-atomic.db");
+        assert!(!std::env::temp_dir()
+            .join("rassay-hyper2-atomic.db.tmp")
+            .exists());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn corrupted_values_fail_verification() {
+        let (path, mut bytes) = write_temp("rassay-hyper2-corrupt.db");
+        bytes[HEADER_SIZE + 5] ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(SubHyperEvaluator::<2>::from_file(&path).is_ok());
+        assert!(matches!(
+            SubHyperEvaluator::<2>::from_file_verified(&path),
+            Err(DatabaseError::ChecksumMismatch { .. })
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn unsupported_version_is_rejected() {
+        let (path, mut bytes) = write_temp("rassay-hyper2-version.db");
+        bytes[4..6].copy_from_slice(&2u16.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            SubHyperEvaluator::<2>::from_file(&path),
+            Err(DatabaseError::UnsupportedVersion(2))
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn other_variant_is_rejected() {
+        let (path, mut bytes) = write_temp("rassay-hyper2-variant.db");
+        bytes[36] = 1;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            SubHyperEvaluator::<2>::from_file(&path),
+            Err(DatabaseError::WrongVariant {
+                expected: 0,
+                found: 1
+            })
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn truncated_file_is_rejected() {
+        let (path, bytes) = write_temp("rassay-hyper2-truncated.db");
+        std::fs::write(&path, &bytes[..bytes.len() - 4]).unwrap();
+        assert!(matches!(
+            SubHyperEvaluator::<2>::from_file(&path),
+            Err(DatabaseError::WrongSize { .. })
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn raw_files_convert() {
+        let (path, bytes) = write_temp("rassay-hyper2-raw.db");
+        // a headerless file, as written before databases were versioned
+        std::fs::write(&path, &bytes[HEADER_SIZE..]).unwrap();
+        assert!(matches!(
+            SubHyperEvaluator::<2>::from_file(&path),
+            Err(DatabaseError::BadMagic)
+        ));
+
+        let possible = SubHyperEvaluator::<2>::possible();
+        let values = read_raw::<f32>(&path, possible).unwrap();
+        assert!(matches!(
+            read_raw::<f32>(&path, possible + 1),
+            Err(DatabaseError::WrongSize { .. })
+        ));
+        SubHyperEvaluator::<2>::write(&path, &values, Metadata::default()).unwrap();
+        let db = SubHyperEvaluator::<2>::from_file_verified(&path).unwrap();
+        assert_eq!(db.value(possible - 1), (possible - 1) as f32);
+        assert_eq!(std::fs::read(&path).unwrap(), bytes);
+        std::fs::remove_file(path).unwrap();
     }
 }
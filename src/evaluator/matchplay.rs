@@ -0,0 +1,55 @@
+use std::marker::PhantomData;
+
+use crate::matchplay::{MatchEquityTable, MatchState};
+use crate::probabilities::Probabilities;
+use bkgm::State;
+
+use super::{Evaluator, PartialEvaluator};
+
+/// Turns the cubeless evaluation of any `Evaluator` into match winning chances.
+pub struct MatchEvaluator<G: State, E: Evaluator<G>> {
+    phantom: PhantomData<G>,
+    evaluator: E,
+    met: MatchEquityTable,
+}
+
+impl<G: State, E: Evaluator<G>> PartialEvaluator<G> for MatchEvaluator<G, E> {
+    fn try_eval(&self, pos: &G) -> f32 {
+        self.evaluator.try_eval(pos)
+    }
+
+    fn best_position(&self, pos: &G, dice: &bkgm::Dice) -> G {
+        self.evaluator.best_position(pos, dice)
+    }
+}
+
+impl<G: State, E: Evaluator<G>> Evaluator<G> for MatchEvaluator<G, E> {
+    fn eval(&self, pos: &G) -> Probabilities {
+        self.evaluator.eval(pos)
+    }
+}
+
+impl<G: State, E: Evaluator<G>> MatchEvaluator<G, E> {
+    pub fn new(evaluator: E, met: MatchEquityTable) -> Self {
+        Self {
+            phantom: PhantomData,
+            evaluator,
+            met,
+        }
+    }
+
+    pub fn met(&self) -> &MatchEquityTable {
+        &self.met
+    }
+
+    /// Match winning chance of the player on roll with the cube on `cube`.
+    /// `None` if the score isn't covered by the match equity table.
+    pub fn mwc(&self, pos: &G, state: &MatchState, cube: u32) -> Option<f32> {
+        self.met.mwc(&self.evaluator.eval(pos), state, cube)
+    }
+
+    /// Equivalent money game equity of the player on roll with the cube on `cube`.
+    pub fn emg(&self, pos: &G, state: &MatchState, cube: u32) -> Option<f32> {
+        self.met.emg(self.mwc(pos, state, cube)?, state, cube)
+    }
+}
@@ -0,0 +1,379 @@
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+use bkgm::dice::ALL_21;
+use bkgm::dice_gen::{DiceGen, FastrandDice};
+use bkgm::GameState::GameOver;
+use bkgm::{Dice, State};
+
+use super::PartialEvaluator;
+
+/// How much searching a single call to `try_eval` or `best_position` may do.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Budget {
+    Iterations(usize),
+    Time(Duration),
+}
+
+/// Rule for picking the move to explore at a decision node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionPolicy {
+    /// UCB1 without prior knowledge, every move is tried once before any is repeated.
+    Uct,
+    /// AlphaZero style selection, with priors from a softmax over the 0-ply evaluations of all moves.
+    Puct,
+}
+
+const DEFAULT_SEED: u64 = 0x5eed;
+const DEFAULT_MAX_NODES: usize = 1 << 20;
+/// Temperature of the softmax turning 0-ply equities into move priors for `SelectionPolicy::Puct`.
+const PRIOR_TEMPERATURE: f32 = 0.1;
+
+/// Position before the dice are rolled. All values are from the perspective of the player on roll.
+struct ChanceNode<G: State> {
+    pos: G,
+    visits: u32,
+    total: f32,
+    /// Evaluation of `pos` if it was already needed for the priors of its parent.
+    estimate: Option<f32>,
+    prior: f32,
+    /// Decision node for every roll of `ALL_21`, created on the second visit.
+    rolls: Option<Vec<usize>>,
+}
+
+impl<G: State> ChanceNode<G> {
+    fn new(pos: G, estimate: Option<f32>, prior: f32) -> Self {
+        Self {
+            pos,
+            visits: 0,
+            total: 0.0,
+            estimate,
+            prior,
+            rolls: None,
+        }
+    }
+
+    fn mean(&self) -> f32 {
+        self.total / self.visits as f32
+    }
+}
+
+/// Position after the dice are rolled, its children being the chance nodes of all legal moves.
+struct DecisionNode<G: State> {
+    pos: G,
+    dice: Dice,
+    visits: u32,
+    children: Option<Vec<usize>>,
+}
+
+struct Tree<G: State> {
+    chance: Vec<ChanceNode<G>>,
+    decision: Vec<DecisionNode<G>>,
+}
+
+impl<G: State> Tree<G> {
+    fn len(&self) -> usize {
+        self.chance.len() + self.decision.len()
+    }
+}
+
+/// Monte Carlo Tree Search with chance nodes for the dice.
+/// New nodes are valued by the inner evaluator instead of random playouts.
+pub struct MctsEvaluator<G: State, E: PartialEvaluator<G>> {
+    phantom: PhantomData<G>,
+    evaluator: E,
+    budget: Budget,
+    policy: SelectionPolicy,
+    exploration: f32,
+    seed: u64,
+    max_nodes: usize,
+}
+
+impl<G: State, E: PartialEvaluator<G>> PartialEvaluator<G> for MctsEvaluator<G, E> {
+    fn try_eval(&self, pos: &G) -> f32 {
+        if let GameOver(result) = pos.game_state() {
+            return result.value();
+        }
+        let mut tree = Tree {
+            chance: vec![ChanceNode::new(*pos, None, 1.0)],
+            decision: Vec::new(),
+        };
+        let mut dice_gen = FastrandDice::with_seed(self.seed);
+        self.run(&mut tree, |tree| {
+            self.simulate_chance(tree, 0, &mut dice_gen);
+        });
+        tree.chance[0].mean()
+    }
+
+    /// Plays the most visited move.
+    fn best_position(&self, pos: &G, dice: &Dice) -> G {
+        let mut tree = Tree {
+            chance: Vec::new(),
+            decision: vec![DecisionNode {
+                pos: *pos,
+                dice: *dice,
+                visits: 0,
+                children: None,
+            }],
+        };
+        let mut dice_gen = FastrandDice::with_seed(self.seed);
+        self.run(&mut tree, |tree| {
+            self.simulate_decision(tree, 0, &mut dice_gen);
+        });
+        let children = tree.decision[0].children.as_ref().unwrap();
+        let best = children
+            .iter()
+            .max_by_key(|&&child| tree.chance[child].visits)
+            .unwrap();
+        tree.chance[*best].pos
+    }
+}
+
+impl<G: State, E: PartialEvaluator<G>> MctsEvaluator<G, E> {
+    pub fn new(evaluator: E, budget: Budget) -> Self {
+        Self {
+            phantom: PhantomData,
+            evaluator,
+            budget,
+            policy: SelectionPolicy::Uct,
+            exploration: 1.0,
+            seed: DEFAULT_SEED,
+            max_nodes: DEFAULT_MAX_NODES,
+        }
+    }
+
+    /// Stops the search once the tree holds `max_nodes` nodes, whatever the budget.
+    /// Defaults to about a million nodes.
+    pub fn with_max_nodes(mut self, max_nodes: usize) -> Self {
+        self.max_nodes = max_nodes;
+        self
+    }
+
+    pub fn with_policy(mut self, policy: SelectionPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Exploration constant of the selection policy, defaults to 1.0.
+    pub fn with_exploration(mut self, exploration: f32) -> Self {
+        self.exploration = exploration;
+        self
+    }
+
+    /// Seed of the dice sampled during the search, the same seed always leads to the same result.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Calls `iteration` until the budget is used up or the tree is full, at least once.
+    fn run(&self, tree: &mut Tree<G>, mut iteration: impl FnMut(&mut Tree<G>)) {
+        let start = Instant::now();
+        let mut iterations = 0;
+        loop {
+            iteration(tree);
+            iterations += 1;
+            let done = match self.budget {
+                Budget::Iterations(budget) => iterations >= budget,
+                Budget::Time(duration) => start.elapsed() >= duration,
+            };
+            if done || tree.len() >= self.max_nodes {
+                break;
+            }
+        }
+    }
+
+    /// Runs one iteration through a chance node and returns the value of the reached leaf
+    /// from the perspective of the player on roll at the chance node.
+    fn simulate_chance(&self, tree: &mut Tree<G>, node: usize, dice_gen: &mut FastrandDice) -> f32 {
+        let pos = tree.chance[node].pos;
+        let value = if let GameOver(result) = pos.game_state() {
+            result.value()
+        } else if tree.chance[node].visits == 0 {
+            match tree.chance[node].estimate {
+                Some(estimate) => estimate,
+                None => self.evaluator.try_eval(&pos),
+            }
+        } else {
+            if tree.chance[node].rolls.is_none() {
+                let rolls = ALL_21
+                    .iter()
+                    .map(|(dice, _)| {
+                        tree.decision.push(DecisionNode {
+                            pos,
+                            dice: *dice,
+                            visits: 0,
+                            children: None,
+                        });
+                        tree.decision.len() - 1
+                    })
+                    .collect();
+                tree.chance[node].rolls = Some(rolls);
+            }
+            let dice = dice_gen.roll();
+            let roll = ALL_21.iter().position(|(d, _)| *d == dice).unwrap();
+            let decision = tree.chance[node].rolls.as_ref().unwrap()[roll];
+            self.simulate_decision(tree, decision, dice_gen)
+        };
+        tree.chance[node].visits += 1;
+        tree.chance[node].total += value;
+        value
+    }
+
+    /// Runs one iteration through a decision node and returns the value for the player to move.
+    fn simulate_decision(
+        &self,
+        tree: &mut Tree<G>,
+        node: usize,
+        dice_gen: &mut FastrandDice,
+    ) -> f32 {
+        if tree.decision[node].children.is_none() {
+            let children = self.expand(tree, node);
+            tree.decision[node].children = Some(children);
+        }
+        let child = self.select(tree, node);
+        let value = -self.simulate_chance(tree, child, dice_gen);
+        tree.decision[node].visits += 1;
+        value
+    }
+
+    /// Creates the chance nodes of all moves, evaluating them for their priors if needed.
+    fn expand(&self, tree: &mut Tree<G>, node: usize) -> Vec<usize> {
+        let DecisionNode { pos, dice, .. } = tree.decision[node];
+        let positions = pos.possible_positions(&dice);
+        let nodes: Vec<ChanceNode<G>> = match self.policy {
+            SelectionPolicy::Uct => positions
+                .into_iter()
+                .map(|pos| ChanceNode::new(pos, None, 1.0))
+                .collect(),
+            SelectionPolicy::Puct => {
+                let estimates: Vec<f32> = positions
+                    .iter()
+                    .map(|pos| match pos.game_state() {
+                        GameOver(result) => result.value(),
+                        _ => self.evaluator.try_eval(pos),
+                    })
+                    .collect();
+                // the best move for the player to move has the lowest estimate
+                let best = estimates.iter().cloned().fold(f32::INFINITY, f32::min);
+                let weights: Vec<f32> = estimates
+                    .iter()
+                    .map(|estimate| ((best - estimate) / PRIOR_TEMPERATURE).exp())
+                    .collect();
+                let sum: f32 = weights.iter().sum();
+                positions
+                    .into_iter()
+                    .zip(estimates)
+                    .zip(weights)
+                    .map(|((pos, estimate), weight)| {
+                        ChanceNode::new(pos, Some(estimate), weight / sum)
+                    })
+                    .collect()
+            }
+        };
+        let first = tree.chance.len();
+        tree.chance.extend(nodes);
+        (first..tree.chance.len()).collect()
+    }
+
+    fn select(&self, tree: &Tree<G>, node: usize) -> usize {
+        let parent_visits = tree.decision[node].visits as f32;
+        let children = tree.decision[node].children.as_ref().unwrap();
+        let score = |child: &ChanceNode<G>| -> f32 {
+            match self.policy {
+                SelectionPolicy::Uct if child.visits == 0 => f32::INFINITY,
+                SelectionPolicy::Uct => {
+                    -child.mean()
+                        + self.exploration * (parent_visits.ln() / child.visits as f32).sqrt()
+                }
+                SelectionPolicy::Puct => {
+                    let value = match child.visits {
+                        0 => -child.estimate.unwrap(),
+                        _ => -child.mean(),
+                    };
+                    value
+                        + self.exploration * child.prior * parent_visits.sqrt()
+                            / (1.0 + child.visits as f32)
+                }
+            }
+        };
+        *children
+            .iter()
+            .max_by(|&&a, &&b| {
+                score(&tree.chance[a])
+                    .partial_cmp(&score(&tree.chance[b]))
+                    .unwrap()
+            })
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::bearoff::TwoSidedBearoff;
+    use crate::evaluator::{Budget, MctsEvaluator, PartialEvaluator, PubEval, SelectionPolicy};
+    use bkgm::{pos, Dice, State, BACKGAMMON};
+
+    const POLICIES: [SelectionPolicy; 2] = [SelectionPolicy::Uct, SelectionPolicy::Puct];
+
+    #[test]
+    fn same_seed_same_result() {
+        for policy in POLICIES {
+            let mcts = MctsEvaluator::new(PubEval::new(), Budget::Iterations(200))
+                .with_policy(policy)
+                .with_seed(7);
+            assert_eq!(mcts.try_eval(&BACKGAMMON), mcts.try_eval(&BACKGAMMON));
+        }
+    }
+
+    #[test]
+    fn plays_legal_moves() {
+        for policy in POLICIES {
+            let mcts =
+                MctsEvaluator::new(PubEval::new(), Budget::Iterations(100)).with_policy(policy);
+            for dice in [Dice::new(3, 1), Dice::new(6, 5), Dice::new(4, 4)] {
+                let best = mcts.best_position(&BACKGAMMON, &dice);
+                assert!(BACKGAMMON.possible_positions(&dice).contains(&best));
+            }
+        }
+    }
+
+    #[test]
+    fn agrees_with_perfect_evaluator() {
+        let pos = pos!(x 6:1, 5:1, 2:1, 1:1; o 19:2, 21:1, 24:1);
+        let dice = Dice::new(4, 1);
+        let bearoff = TwoSidedBearoff::generate(4);
+        let best = bearoff.best_position(&pos, &dice);
+        for policy in POLICIES {
+            let perfect = TwoSidedBearoff::generate(4);
+            let mcts = MctsEvaluator::new(perfect, Budget::Iterations(2000)).with_policy(policy);
+            assert_eq!(mcts.best_position(&pos, &dice), best);
+        }
+    }
+
+    #[test]
+    fn converges_to_perfect_evaluator() {
+        let pos = pos!(x 6:1, 5:1, 2:1, 1:1; o 19:2, 21:1, 24:1);
+        let bearoff = TwoSidedBearoff::generate(4);
+        let exact = bearoff.try_eval(&pos);
+        let error = |iterations| {
+            let perfect = TwoSidedBearoff::generate(4);
+            let mcts = MctsEvaluator::new(perfect, Budget::Iterations(iterations))
+                .with_policy(SelectionPolicy::Puct);
+            (mcts.try_eval(&pos) - exact).abs()
+        };
+        assert_eq!(error(1), 0.0);
+        // Uct averages in every move it tries once, so only Puct gets close this quickly
+        assert!(error(5000) < 0.02);
+    }
+
+    #[test]
+    fn tree_size_is_capped() {
+        let mcts = MctsEvaluator::new(PubEval::new(), Budget::Time(Duration::from_secs(60)))
+            .with_max_nodes(500);
+        // would take a minute without the cap
+        mcts.try_eval(&BACKGAMMON);
+    }
+}
@@ -1,17 +1,35 @@
+mod cache;
+mod cubeful;
+pub mod database;
+mod ensemble;
 mod evaluator;
 mod greedy;
 mod hyper;
+mod matchplay;
+mod mcts;
 // mod nnevaluator;
 mod nply;
+mod phase;
 mod pubeval;
+mod race;
 mod rollout;
+mod softmax;
 mod subhyper;
 
+pub use cache::{CacheStats, CachedEvaluator, EvalCache};
+pub use cubeful::CubefulEvaluator;
+pub use database::{DatabaseError, DatabaseHeader, DatabaseValue, Metadata};
+pub use ensemble::EnsembleEvaluator;
 pub use evaluator::{Evaluator, PartialEvaluator, RandomEvaluator};
 pub use greedy::GreedyEvaluator;
 pub use hyper::HyperEvaluator;
+pub use matchplay::MatchEvaluator;
+pub use mcts::{Budget, MctsEvaluator, SelectionPolicy};
 // pub use nnevaluator::NNEvaluator;
-pub use nply::PlyEvaluator;
+pub use nply::{MoveFilter, PlyEvaluator, SearchMode, SearchStats};
+pub use phase::{PhaseEvaluator, PositionClass};
 pub use pubeval::PubEval;
-pub use rollout::RolloutEvaluator;
+pub use race::{RaceCounts, RaceEvaluator, RaceFormula};
+pub use rollout::{ParallelRolloutEvaluator, RolloutEstimate, RolloutEvaluator, RolloutResult};
+pub use softmax::{SoftmaxEvaluator, TemperatureSchedule};
 pub use subhyper::SubHyperEvaluator;
@@ -1,51 +1,124 @@
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
-use bkgm::{dice::ALL_21, GameState::GameOver, State};
+use crate::probabilities::Probabilities;
+use bkgm::{dice::ALL_21, Dice, GameResult, GameState::GameOver, State};
 
-use super::{Evaluator, PartialEvaluator};
+use super::cache::cached_batch;
+use super::{EvalCache, Evaluator, PartialEvaluator};
+
+/// gnubg style move filter: keeps the best `accept` candidates according to the 0-ply evaluation,
+/// plus any other candidate within `threshold` equity of the best one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MoveFilter {
+    pub accept: usize,
+    pub threshold: f32,
+}
+
+impl MoveFilter {
+    pub fn new(accept: usize, threshold: f32) -> Self {
+        assert!(
+            accept > 0,
+            "a move filter must accept at least one candidate"
+        );
+        Self { accept, threshold }
+    }
+
+    /// `values` are the evaluations of the candidates from the opponent's perspective,
+    /// so smaller is better for the player making the move.
+    fn apply<G: State>(&self, candidates: Vec<G>, values: Vec<f32>) -> Vec<G> {
+        let mut ranked: Vec<(G, f32)> = candidates.into_iter().zip(values).collect();
+        ranked.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        let best = ranked[0].1;
+        ranked
+            .into_iter()
+            .enumerate()
+            .take_while(|(i, (_, value))| *i < self.accept || *value - best <= self.threshold)
+            .map(|(_, (pos, _))| pos)
+            .collect()
+    }
+}
+
+/// Search algorithm of `PlyEvaluator`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchMode {
+    /// Evaluates every roll and every candidate.
+    Expectimax,
+    /// Ballard's Star1: alpha-beta windows at chance nodes, using the bounds of the equity.
+    Star1,
+    /// Ballard's Star2: Star1 plus probing one candidate per roll for a lower bound first.
+    Star2,
+}
+
+/// Number of searched nodes and of calls to the inner evaluator since the last reset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    pub nodes: usize,
+    pub evaluations: usize,
+}
 
 pub struct PlyEvaluator<G: State, E: PartialEvaluator<G>> {
     phantom: PhantomData<G>,
     evaluator: E,
     depth: usize,
-    top_k: Option<usize>,
+    /// `filters[d]` restricts the candidates searched at nodes with `d` plies remaining.
+    filters: Vec<Option<MoveFilter>>,
+    mode: SearchMode,
+    /// Lower and upper bound of any value of the search, see `with_bounds`.
+    bounds: (f32, f32),
+    nodes: AtomicUsize,
+    evaluations: AtomicUsize,
+    cache: Option<Arc<EvalCache<G, f32>>>,
 }
 
 impl<G: State, E: PartialEvaluator<G>> PartialEvaluator<G> for PlyEvaluator<G, E> {
     fn try_eval(&self, pos: &G) -> f32 {
-        self.ply(pos, self.depth)
+        self.search(pos, self.depth)
     }
 
     fn best_position(&self, pos: &G, dice: &bkgm::Dice) -> G {
-        *pos.possible_positions(dice)
-            .iter()
-            .map(|pos| (pos, self.ply(pos, self.depth - 1)))
-            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
-            .unwrap()
-            .0
+        let candidates = self.candidates(pos, dice, self.depth);
+        match self.mode {
+            SearchMode::Expectimax => {
+                let values = match self.depth {
+                    1 => self.leaves(&candidates),
+                    _ => candidates
+                        .iter()
+                        .map(|pos| self.ply(pos, self.depth - 1))
+                        .collect(),
+                };
+                *candidates
+                    .iter()
+                    .zip(values)
+                    .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+                    .unwrap()
+                    .0
+            }
+            SearchMode::Star1 | SearchMode::Star2 => {
+                let (lower, upper) = self.bounds;
+                let mut alpha = lower;
+                let mut best = (candidates[0], f32::NEG_INFINITY);
+                for child in self.ordered(candidates, self.depth) {
+                    let value = -self.star(&child, self.depth - 1, -upper, -alpha);
+                    if value > best.1 {
+                        best = (child, value);
+                        alpha = alpha.max(value);
+                    }
+                }
+                best.0
+            }
+        }
     }
 }
 
-// impl<G: State, E: Evaluator<G>> Evaluator<G> for PlyEvaluator<G, E> {
-//     fn eval(&self, pos: &G) -> Probabilities {
-//         self.evaluator.eval(pos)
-//         for _ in 0..self.num_rollouts {
-//             let mut pos = pos.clone();
-//             let mut dice = bkgm::Dice::new();
-//             while !pos.is_game_over() {
-//                 let best_pos = self.best_position(&pos, &dice);
-//                 pos = best_pos;
-//                 dice = bkgm::Dice::new();
-//             }
-//             let winner = pos.winner();
-//             if winner == bkgm::Player::White {
-//                 white_wins += 1;
-//             } else {
-//                 black_wins += 1;
-//             }
-//         }
-//     }
-// }
+impl<G: State, E: Evaluator<G>> Evaluator<G> for PlyEvaluator<G, E> {
+    /// Expectimax over the full probabilities, picking moves by the equity of the inner evaluator.
+    /// Always searches every candidate, regardless of the search mode.
+    fn eval(&self, pos: &G) -> Probabilities {
+        self.ply_probabilities(pos, self.depth)
+    }
+}
 
 impl<G: State, E: PartialEvaluator<G>> PlyEvaluator<G, E> {
     pub fn new(evaluator: E, depth: usize) -> Self {
@@ -54,27 +127,456 @@ impl<G: State, E: PartialEvaluator<G>> PlyEvaluator<G, E> {
             phantom: PhantomData,
             evaluator,
             depth,
-            top_k: None,
+            filters: vec![None; depth + 1],
+            mode: SearchMode::Expectimax,
+            bounds: (-3.0, 3.0),
+            nodes: AtomicUsize::new(0),
+            evaluations: AtomicUsize::new(0),
+            cache: None,
+        }
+    }
+
+    /// Caches leaf evaluations and the values of searched nodes per remaining depth.
+    /// Only share the cache between evaluators with the same inner evaluator and filters.
+    pub fn with_cache(mut self, cache: Arc<EvalCache<G, f32>>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn with_search(mut self, mode: SearchMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Bounds of the values returned by the inner evaluator, used by the Star searches.
+    /// Results of finished games are clamped to the same bounds in every search mode, so with
+    /// bounds of -1.0 and 1.0 gammons and backgammons count as single wins.
+    /// Defaults to the cubeless equity bounds of -3.0 and 3.0.
+    pub fn with_bounds(mut self, lower: f32, upper: f32) -> Self {
+        assert!(
+            lower < upper,
+            "lower bound must be smaller than upper bound"
+        );
+        self.bounds = (lower, upper);
+        self
+    }
+
+    pub fn stats(&self) -> SearchStats {
+        SearchStats {
+            nodes: self.nodes.load(Ordering::Relaxed),
+            evaluations: self.evaluations.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn reset_stats(&self) {
+        self.nodes.store(0, Ordering::Relaxed);
+        self.evaluations.store(0, Ordering::Relaxed);
+    }
+
+    fn evaluate(&self, pos: &G) -> f32 {
+        if let Some(value) = self.cached(pos, 0) {
+            return value;
+        }
+        self.evaluations.fetch_add(1, Ordering::Relaxed);
+        let value = self.evaluator.try_eval(pos);
+        self.store(pos, 0, value);
+        value
+    }
+
+    /// Evaluates all `positions` with a single batch call for those that aren't cached.
+    fn evaluate_batch(&self, positions: &[G]) -> Vec<f32> {
+        if positions.is_empty() {
+            return Vec::new();
+        }
+        let evaluate = |missing: &[G]| {
+            self.evaluations.fetch_add(missing.len(), Ordering::Relaxed);
+            self.evaluator.try_eval_batch(missing)
+        };
+        match &self.cache {
+            Some(cache) => cached_batch(cache, positions, evaluate),
+            None => evaluate(positions),
+        }
+    }
+
+    /// Values of candidates that are leaves of the search, evaluating the unfinished games as one batch.
+    fn leaves(&self, candidates: &[G]) -> Vec<f32> {
+        self.nodes.fetch_add(candidates.len(), Ordering::Relaxed);
+        let ongoing: Vec<G> = candidates
+            .iter()
+            .filter(|pos| !matches!(pos.game_state(), GameOver(_)))
+            .copied()
+            .collect();
+        let mut evaluated = self.evaluate_batch(&ongoing).into_iter();
+        candidates
+            .iter()
+            .map(|pos| match pos.game_state() {
+                GameOver(result) => self.terminal(&result),
+                _ => evaluated.next().unwrap(),
+            })
+            .collect()
+    }
+
+    fn cached(&self, pos: &G, depth: usize) -> Option<f32> {
+        self.cache.as_ref().and_then(|cache| cache.get(pos, depth))
+    }
+
+    fn store(&self, pos: &G, depth: usize, value: f32) {
+        if let Some(cache) = &self.cache {
+            cache.insert(pos, depth, value);
         }
     }
 
+    fn search(&self, pos: &G, depth: usize) -> f32 {
+        match self.mode {
+            SearchMode::Expectimax => self.ply(pos, depth),
+            SearchMode::Star1 | SearchMode::Star2 => {
+                self.star(pos, depth, self.bounds.0, self.bounds.1)
+            }
+        }
+    }
+
+    /// Applies `filter` at every node with `depth` plies remaining.
+    /// Filters only matter where the candidates are searched deeper, so for `depth` of 2 or more.
+    pub fn with_filter(mut self, depth: usize, filter: MoveFilter) -> Self {
+        assert!(depth <= self.depth, "filter depth exceeds search depth");
+        self.filters[depth] = Some(filter);
+        self
+    }
+
+    /// Applies the same `filter` at every depth of the search.
+    pub fn with_filters(mut self, filter: MoveFilter) -> Self {
+        self.filters = vec![Some(filter); self.depth + 1];
+        self
+    }
+
+    /// Legal moves worth searching at a node with `depth` plies remaining.
+    fn candidates(&self, pos: &G, dice: &Dice, depth: usize) -> Vec<G> {
+        let positions = pos.possible_positions(dice);
+        match self.filters[depth] {
+            Some(filter) if depth > 1 && positions.len() > filter.accept => {
+                let values = self.evaluate_batch(&positions);
+                filter.apply(positions, values)
+            }
+            _ => positions,
+        }
+    }
+
+    /// Candidates sorted by their 0-ply evaluation, best first, where it pays off for the Star searches.
+    fn ordered(&self, candidates: Vec<G>, depth: usize) -> Vec<G> {
+        if depth < 2 || candidates.len() < 2 {
+            return candidates;
+        }
+        let values = self.evaluate_batch(&candidates);
+        let mut ranked: Vec<(G, f32)> = candidates.into_iter().zip(values).collect();
+        ranked.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        ranked.into_iter().map(|(pos, _)| pos).collect()
+    }
+
+    /// Value of a finished game, within the bounds of the search.
+    fn terminal(&self, result: &GameResult) -> f32 {
+        result.value().clamp(self.bounds.0, self.bounds.1)
+    }
+
     fn ply(&self, pos: &G, depth: usize) -> f32 {
-        if depth == 0 {
-            return self.evaluator.try_eval(pos);
-        } else if let GameOver(result) = pos.game_state() {
-            return result.value();
+        self.nodes.fetch_add(1, Ordering::Relaxed);
+        if let GameOver(result) = pos.game_state() {
+            return self.terminal(&result);
+        } else if depth == 0 {
+            return self.evaluate(pos);
+        } else if let Some(value) = self.cached(pos, depth) {
+            return value;
         }
+        let value = self.expectimax(pos, depth);
+        self.store(pos, depth, value);
+        value
+    }
+
+    fn expectimax(&self, pos: &G, depth: usize) -> f32 {
         let mut result = 0.0;
         for (dice, prob) in ALL_21 {
-            let mut best_value = f32::NEG_INFINITY;
-            for pos in pos.possible_positions(&dice) {
-                let value = -self.ply(&pos, depth - 1);
-                if value > best_value {
-                    best_value = value;
-                }
-            }
+            let candidates = self.candidates(pos, &dice, depth);
+            let values = match depth {
+                1 => self.leaves(&candidates),
+                _ => candidates
+                    .iter()
+                    .map(|pos| self.ply(pos, depth - 1))
+                    .collect(),
+            };
+            let best_value = values
+                .into_iter()
+                .map(|value| -value)
+                .fold(f32::NEG_INFINITY, f32::max);
             result += (prob as f32) * best_value;
         }
         result / 36.0
     }
+
+    /// Like `leaves`, but with the probabilities of the inner evaluator.
+    fn leaf_probabilities(&self, candidates: &[G]) -> Vec<Probabilities>
+    where
+        E: Evaluator<G>,
+    {
+        self.nodes.fetch_add(candidates.len(), Ordering::Relaxed);
+        let ongoing: Vec<G> = candidates
+            .iter()
+            .filter(|pos| !matches!(pos.game_state(), GameOver(_)))
+            .copied()
+            .collect();
+        let mut evaluated = match ongoing.is_empty() {
+            true => Vec::new(),
+            false => {
+                self.evaluations.fetch_add(ongoing.len(), Ordering::Relaxed);
+                self.evaluator.eval_batch(&ongoing)
+            }
+        }
+        .into_iter();
+        candidates
+            .iter()
+            .map(|pos| match pos.game_state() {
+                GameOver(result) => Probabilities::from_result(&result),
+                _ => evaluated.next().unwrap(),
+            })
+            .collect()
+    }
+
+    /// Probabilities of the player on roll in `pos`, flipping the probabilities of the best move for every roll.
+    /// The search cache only holds equities, so probabilities are never cached.
+    fn ply_probabilities(&self, pos: &G, depth: usize) -> Probabilities
+    where
+        E: Evaluator<G>,
+    {
+        self.nodes.fetch_add(1, Ordering::Relaxed);
+        if let GameOver(result) = pos.game_state() {
+            return Probabilities::from_result(&result);
+        } else if depth == 0 {
+            self.evaluations.fetch_add(1, Ordering::Relaxed);
+            return self.evaluator.eval(pos);
+        }
+        let mut result = Probabilities::empty();
+        for (dice, n) in ALL_21 {
+            let candidates = self.candidates(pos, &dice, depth);
+            let probabilities = match depth {
+                1 => self.leaf_probabilities(&candidates),
+                _ => candidates
+                    .iter()
+                    .map(|child| self.ply_probabilities(child, depth - 1))
+                    .collect(),
+            };
+            let best = probabilities
+                .into_iter()
+                .min_by(|a, b| a.equity().partial_cmp(&b.equity()).unwrap())
+                .unwrap();
+            result = result + best.flip() * (n as f32 / 36.0);
+        }
+        result
+    }
+
+    /// Expectimax value of `pos` with fail-soft alpha-beta pruning at chance nodes.
+    /// The result is exact if it lies within `alpha` and `beta`, an upper bound if it is
+    /// at most `alpha` and a lower bound if it is at least `beta`.
+    fn star(&self, pos: &G, depth: usize, alpha: f32, beta: f32) -> f32 {
+        self.nodes.fetch_add(1, Ordering::Relaxed);
+        if let GameOver(result) = pos.game_state() {
+            return self.terminal(&result);
+        } else if depth == 0 {
+            return self.evaluate(pos);
+        } else if let Some(value) = self.cached(pos, depth) {
+            return value;
+        }
+        let value = self.star_search(pos, depth, alpha, beta);
+        // bounds from a cutoff are only valid for this window
+        if alpha < value && value < beta {
+            self.store(pos, depth, value);
+        }
+        value
+    }
+
+    fn star_search(&self, pos: &G, depth: usize, alpha: f32, beta: f32) -> f32 {
+        let (lower, upper) = self.bounds;
+
+        let rolls: Vec<(f32, Vec<G>)> = ALL_21
+            .iter()
+            .map(|(dice, n)| {
+                let candidates = self.candidates(pos, dice, depth);
+                (*n as f32 / 36.0, self.ordered(candidates, depth))
+            })
+            .collect();
+
+        // Lower bound of the value of every roll, and the exact value of the probed move if known
+        let mut bounds = vec![lower; rolls.len()];
+        let mut probes = vec![None; rolls.len()];
+        if self.mode == SearchMode::Star2 && depth > 1 {
+            let mut probed = 0.0;
+            let mut unprobed_mass = 1.0;
+            for (i, (prob, candidates)) in rolls.iter().enumerate() {
+                unprobed_mass -= prob;
+                // value this roll needs for a cutoff, even if all remaining rolls turn out best possible
+                let target = (beta - probed - unprobed_mass * upper) / prob;
+                let probe_beta = target.clamp(lower, upper);
+                let value = -self.star(&candidates[0], depth - 1, -probe_beta, -lower);
+                if lower < value && value < probe_beta {
+                    probes[i] = Some(value);
+                }
+                bounds[i] = value.max(lower);
+                probed += prob * bounds[i];
+            }
+            if probed >= beta {
+                return probed;
+            }
+        }
+
+        let mut sum = 0.0;
+        let mut remaining_lower: f32 = rolls
+            .iter()
+            .zip(&bounds)
+            .map(|((prob, _), bound)| prob * bound)
+            .sum();
+        let mut remaining_mass = 1.0;
+        for (((prob, candidates), bound), probe) in rolls.iter().zip(&bounds).zip(probes) {
+            remaining_lower -= prob * bound;
+            remaining_mass -= prob;
+            // this roll's value at or below `a` fails low, at or above `b` fails high
+            let a = (alpha - sum - remaining_mass * upper) / prob;
+            let b = (beta - sum - remaining_lower) / prob;
+            if a >= upper {
+                return sum + prob * upper + remaining_mass * upper;
+            } else if b <= lower {
+                return sum + prob * lower + remaining_lower;
+            }
+            let value = self.max_node(candidates, depth, a.max(lower), b.min(upper), probe);
+            if value >= b {
+                return sum + prob * value + remaining_lower;
+            } else if value <= a {
+                return sum + prob * value + remaining_mass * upper;
+            }
+            sum += prob * value;
+        }
+        sum
+    }
+
+    /// Best value among `candidates` for the player making the move, fail-soft.
+    /// Leaves are evaluated as one batch, giving up the cutoffs among them.
+    /// `probe` is the exact value of the first candidate, if Star2 probing found it.
+    fn max_node(
+        &self,
+        candidates: &[G],
+        depth: usize,
+        alpha: f32,
+        beta: f32,
+        probe: Option<f32>,
+    ) -> f32 {
+        if depth == 1 {
+            return self
+                .leaves(candidates)
+                .into_iter()
+                .map(|value| -value)
+                .fold(f32::NEG_INFINITY, f32::max);
+        }
+        let mut alpha = alpha;
+        let mut best = f32::NEG_INFINITY;
+        for (i, child) in candidates.iter().enumerate() {
+            let value = match probe {
+                Some(value) if i == 0 => value,
+                _ => -self.star(child, depth - 1, -beta, -alpha),
+            };
+            if value > best {
+                best = value;
+                if best >= beta {
+                    return best;
+                }
+                alpha = alpha.max(best);
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{Evaluator, PartialEvaluator, PlyEvaluator, SearchMode};
+    use crate::probabilities::Probabilities;
+    use bkgm::{pos, GameState::GameOver, State, HYPERGAMMON};
+
+    /// Bounded and deterministic, which is all the Star searches need.
+    struct PipEvaluator;
+
+    impl<G: State> PartialEvaluator<G> for PipEvaluator {
+        fn try_eval(&self, pos: &G) -> f32 {
+            let mut x_pips = 25.0 * pos.x_bar() as f32;
+            let mut o_pips = 25.0 * pos.o_bar() as f32;
+            for i in 1..=24 {
+                let pip = pos.pip(i) as f32;
+                if pip > 0.0 {
+                    x_pips += pip * i as f32;
+                } else {
+                    o_pips -= pip * (25 - i) as f32;
+                }
+            }
+            ((o_pips - x_pips) / 20.0).tanh()
+        }
+    }
+
+    impl<G: State> Evaluator<G> for PipEvaluator {
+        fn eval(&self, pos: &G) -> Probabilities {
+            assert!(
+                !matches!(pos.game_state(), GameOver(_)),
+                "finished games are never evaluated"
+            );
+            let win = (1.0 + self.try_eval(pos)) / 2.0;
+            Probabilities {
+                win_n: win,
+                win_g: 0.0,
+                win_b: 0.0,
+                lose_n: 1.0 - win,
+                lose_g: 0.0,
+                lose_b: 0.0,
+            }
+        }
+    }
+
+    #[test]
+    fn equity_matches_probabilities() {
+        // most rolls bear off both checkers, ending the game at the leaves
+        let pos = pos!(x 2:1, 1:1; o 24:1, 23:1);
+        for depth in [1, 2] {
+            let ply = PlyEvaluator::new(PipEvaluator, depth);
+            assert!((ply.try_eval(&pos) - ply.eval(&pos).equity()).abs() < 1e-5);
+        }
+
+        let ply = PlyEvaluator::new(PipEvaluator, 1);
+        ply.eval(&pos);
+        let stats = ply.stats();
+        assert!(0 < stats.evaluations && stats.evaluations < stats.nodes - 1);
+    }
+
+    #[test]
+    fn star_searches_match_expectimax() {
+        let expectimax = PlyEvaluator::new(PipEvaluator, 2);
+        let expected = expectimax.try_eval(&HYPERGAMMON);
+        for mode in [SearchMode::Star1, SearchMode::Star2] {
+            let star = PlyEvaluator::new(PipEvaluator, 2)
+                .with_search(mode)
+                .with_bounds(-1.0, 1.0);
+            let value = star.try_eval(&HYPERGAMMON);
+            assert!((value - expected).abs() < 1e-4);
+            assert!(star.stats().nodes < expectimax.stats().nodes);
+        }
+    }
+
+    #[test]
+    fn gammons_are_clamped_to_bounds() {
+        // both checkers are borne off with most rolls, winning a gammon
+        let pos = pos!(x 5:1, 3:1; o 12:15);
+        for bounds in [(-1.0, 1.0), (-3.0, 3.0)] {
+            let expectimax = PlyEvaluator::new(PipEvaluator, 2).with_bounds(bounds.0, bounds.1);
+            let expected = expectimax.try_eval(&pos);
+            assert!(expected <= bounds.1);
+            for mode in [SearchMode::Star1, SearchMode::Star2] {
+                let star = PlyEvaluator::new(PipEvaluator, 2)
+                    .with_search(mode)
+                    .with_bounds(bounds.0, bounds.1);
+                assert!((star.try_eval(&pos) - expected).abs() < 1e-4);
+            }
+        }
+    }
 }
@@ -0,0 +1,218 @@
+use std::marker::PhantomData;
+
+use crate::bearoff::home_boards;
+use crate::probabilities::Probabilities;
+use bkgm::position::{GamePhase, OngoingPhase};
+use bkgm::State;
+
+use super::{Evaluator, PartialEvaluator};
+
+/// Class of a position, each class being evaluated by its own evaluator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PositionClass {
+    Contact,
+    Race,
+    /// No contact and all checkers of both sides in their home boards.
+    Bearoff,
+}
+
+impl PositionClass {
+    pub const ALL: [PositionClass; 3] = [
+        PositionClass::Contact,
+        PositionClass::Race,
+        PositionClass::Bearoff,
+    ];
+
+    /// Classifies by `GamePhase`, splitting off bearoffs from races.
+    /// Finished games count as contact, so they go to the main evaluator.
+    pub fn classify<G: State>(pos: &G) -> Self {
+        match pos.phase() {
+            GamePhase::Ongoing(OngoingPhase::Race) if home_boards(pos).is_some() => {
+                PositionClass::Bearoff
+            }
+            GamePhase::Ongoing(OngoingPhase::Race) => PositionClass::Race,
+            _ => PositionClass::Contact,
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        match self {
+            PositionClass::Contact => 0,
+            PositionClass::Race => 1,
+            PositionClass::Bearoff => 2,
+        }
+    }
+}
+
+/// Delegates every position to the evaluator of its class.
+pub struct PhaseEvaluator<G: State, C, R, B> {
+    phantom: PhantomData<G>,
+    contact: C,
+    race: R,
+    bearoff: B,
+    classifier: fn(&G) -> PositionClass,
+}
+
+impl<G, C, R, B> PartialEvaluator<G> for PhaseEvaluator<G, C, R, B>
+where
+    G: State,
+    C: PartialEvaluator<G>,
+    R: PartialEvaluator<G>,
+    B: PartialEvaluator<G>,
+{
+    fn try_eval(&self, pos: &G) -> f32 {
+        match self.class(pos) {
+            PositionClass::Contact => self.contact.try_eval(pos),
+            PositionClass::Race => self.race.try_eval(pos),
+            PositionClass::Bearoff => self.bearoff.try_eval(pos),
+        }
+    }
+
+    /// Evaluates the positions of every class as one batch.
+    fn try_eval_batch(&self, positions: &[G]) -> Vec<f32> {
+        self.batched(
+            positions,
+            |positions| self.contact.try_eval_batch(positions),
+            |positions| self.race.try_eval_batch(positions),
+            |positions| self.bearoff.try_eval_batch(positions),
+        )
+    }
+}
+
+impl<G, C, R, B> Evaluator<G> for PhaseEvaluator<G, C, R, B>
+where
+    G: State,
+    C: Evaluator<G>,
+    R: Evaluator<G>,
+    B: Evaluator<G>,
+{
+    fn eval(&self, pos: &G) -> Probabilities {
+        match self.class(pos) {
+            PositionClass::Contact => self.contact.eval(pos),
+            PositionClass::Race => self.race.eval(pos),
+            PositionClass::Bearoff => self.bearoff.eval(pos),
+        }
+    }
+
+    fn eval_batch(&self, positions: &[G]) -> Vec<Probabilities> {
+        self.batched(
+            positions,
+            |positions| self.contact.eval_batch(positions),
+            |positions| self.race.eval_batch(positions),
+            |positions| self.bearoff.eval_batch(positions),
+        )
+    }
+}
+
+impl<G: State, C, R, B> PhaseEvaluator<G, C, R, B> {
+    pub fn new(contact: C, race: R, bearoff: B) -> Self {
+        Self {
+            phantom: PhantomData,
+            contact,
+            race,
+            bearoff,
+            classifier: PositionClass::classify,
+        }
+    }
+
+    /// Replaces `PositionClass::classify`, e.g. to treat bearoffs beyond a database as races.
+    pub fn with_classifier(mut self, classifier: fn(&G) -> PositionClass) -> Self {
+        self.classifier = classifier;
+        self
+    }
+
+    pub fn class(&self, pos: &G) -> PositionClass {
+        (self.classifier)(pos)
+    }
+
+    /// Splits `positions` by class, evaluates each class with a single call and restores the order.
+    fn batched<V: Copy>(
+        &self,
+        positions: &[G],
+        contact: impl Fn(&[G]) -> Vec<V>,
+        race: impl Fn(&[G]) -> Vec<V>,
+        bearoff: impl Fn(&[G]) -> Vec<V>,
+    ) -> Vec<V> {
+        let classes: Vec<PositionClass> = positions.iter().map(|pos| self.class(pos)).collect();
+        let mut values: Vec<Option<V>> = vec![None; positions.len()];
+        for class in PositionClass::ALL {
+            let indices: Vec<usize> = (0..positions.len())
+                .filter(|i| classes[*i] == class)
+                .collect();
+            if indices.is_empty() {
+                continue;
+            }
+            let group: Vec<G> = indices.iter().map(|i| positions[*i]).collect();
+            let evaluated = match class {
+                PositionClass::Contact => contact(&group),
+                PositionClass::Race => race(&group),
+                PositionClass::Bearoff => bearoff(&group),
+            };
+            for (i, value) in indices.into_iter().zip(evaluated) {
+                values[i] = Some(value);
+            }
+        }
+        values.into_iter().map(Option::unwrap).collect()
+    }
+}
+
+impl<G: State, E> PhaseEvaluator<G, E, E, E> {
+    /// Evaluator of `class`, if all classes use the same type of evaluator.
+    pub fn evaluator(&self, class: PositionClass) -> &E {
+        match class {
+            PositionClass::Contact => &self.contact,
+            PositionClass::Race => &self.race,
+            PositionClass::Bearoff => &self.bearoff,
+        }
+    }
+
+    pub fn evaluator_mut(&mut self, class: PositionClass) -> &mut E {
+        match class {
+            PositionClass::Contact => &mut self.contact,
+            PositionClass::Race => &mut self.race,
+            PositionClass::Bearoff => &mut self.bearoff,
+        }
+    }
+
+    /// The evaluators ordered like `PositionClass::ALL`.
+    pub fn into_evaluators(self) -> Vec<E> {
+        vec![self.contact, self.race, self.bearoff]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{PartialEvaluator, PhaseEvaluator, PositionClass};
+    use bkgm::{pos, BACKGAMMON};
+
+    struct Constant(f32);
+
+    impl<G: bkgm::State> PartialEvaluator<G> for Constant {
+        fn try_eval(&self, _pos: &G) -> f32 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn classify() {
+        let race = pos!(x 12:1; o 19:1);
+        let bearoff = pos!(x 3:1; o 22:1);
+        let finished = pos!(x 1:0; o 19:1);
+        assert_eq!(PositionClass::classify(&BACKGAMMON), PositionClass::Contact);
+        assert_eq!(PositionClass::classify(&race), PositionClass::Race);
+        assert_eq!(PositionClass::classify(&bearoff), PositionClass::Bearoff);
+        assert_eq!(PositionClass::classify(&finished), PositionClass::Contact);
+    }
+
+    #[test]
+    fn batches_keep_order() {
+        let race = pos!(x 12:1; o 19:1);
+        let bearoff = pos!(x 3:1; o 22:1);
+        let evaluator = PhaseEvaluator::new(Constant(0.0), Constant(1.0), Constant(2.0));
+        let positions = [race, BACKGAMMON, bearoff, BACKGAMMON, race];
+        assert_eq!(
+            evaluator.try_eval_batch(&positions),
+            vec![1.0, 0.0, 2.0, 0.0, 1.0]
+        );
+    }
+}
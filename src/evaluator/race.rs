@@ -0,0 +1,281 @@
+use crate::bearoff::{Board, OneSidedBearoff};
+use crate::cube::CubeAction;
+use crate::probabilities::Probabilities;
+use bkgm::State;
+
+use super::{Evaluator, PartialEvaluator};
+
+/// Average number of pips moved per roll.
+const PIPS_PER_ROLL: f32 = 49.0 / 6.0;
+/// Variance of the number of rolls needed per effective pip, fitted on the one-sided bearoff
+/// database. Wastage makes it smaller than the variance of the pips rolled would suggest.
+const VARIANCE_PER_PIP: f32 = 0.018;
+/// Wastage of a smooth position, on top of the penalties of the Keith count.
+const BASE_WASTAGE: f32 = 7.0;
+
+/// Checkers of one side by distance from bearing off, `checkers[25]` being the bar.
+type Side = [u8; 26];
+
+fn sides<G: State>(pos: &G) -> (Side, Side) {
+    let mut x = [0; 26];
+    let mut o = [0; 26];
+    x[25] = pos.x_bar();
+    o[25] = pos.o_bar();
+    for i in 1..=24 {
+        let pip = pos.pip(i);
+        if pip > 0 {
+            x[i] = pip as u8;
+        } else if pip < 0 {
+            o[25 - i] = -pip as u8;
+        }
+    }
+    (x, o)
+}
+
+fn pips(side: &Side) -> u32 {
+    side.iter()
+        .enumerate()
+        .map(|(i, n)| i as u32 * *n as u32)
+        .sum()
+}
+
+fn home_board(side: &Side) -> Option<Board> {
+    match side[7..].iter().all(|n| *n == 0) {
+        true => Some(side[1..7].try_into().unwrap()),
+        false => None,
+    }
+}
+
+/// Pip count plus 2 for every checker on the 1-point beyond the first, 1 for every checker
+/// on the 2-point beyond the first and on the 3-point beyond the third, and 1 for every
+/// empty 4, 5 or 6-point.
+fn keith(side: &Side) -> f32 {
+    let mut count = pips(side) as f32;
+    count += 2.0 * side[1].saturating_sub(1) as f32;
+    count += side[2].saturating_sub(1) as f32;
+    count += side[3].saturating_sub(3) as f32;
+    count += side[4..=6].iter().filter(|n| **n == 0).count() as f32;
+    count
+}
+
+/// Pip count plus 2 for every checker left, plus 1 for every checker on the 1-point,
+/// minus 1 for every occupied home board point.
+fn thorp(side: &Side) -> f32 {
+    let checkers: u32 = side.iter().map(|n| *n as u32).sum();
+    let occupied = side[1..=6].iter().filter(|n| **n > 0).count() as u32;
+    (pips(side) + 2 * checkers + side[1] as u32) as f32 - occupied as f32
+}
+
+/// Standard normal cumulative distribution, Abramowitz and Stegun 7.1.26.
+fn normal_cdf(x: f32) -> f32 {
+    let z = x.abs() / std::f32::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.3275911 * z);
+    let poly =
+        t * (0.2548296 + t * (-0.28449672 + t * (1.4214138 + t * (-1.4531521 + t * 1.0614054))));
+    let erf = 1.0 - poly * (-z * z).exp();
+    if x >= 0.0 {
+        0.5 * (1.0 + erf)
+    } else {
+        0.5 * (1.0 - erf)
+    }
+}
+
+/// Race formulas for cube decisions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RaceFormula {
+    Keith,
+    Thorp,
+    /// Double with a lead of 8%, redouble with 9%, pass with more than 12%.
+    EightNineTwelve,
+}
+
+/// Pip counts of both sides, from the perspective of the player on roll.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RaceCounts {
+    pub pips: u32,
+    pub opp_pips: u32,
+    pub effective: f32,
+    pub opp_effective: f32,
+    pub keith: f32,
+    pub opp_keith: f32,
+    pub thorp: f32,
+    pub opp_thorp: f32,
+}
+
+/// Evaluates races with a normal approximation over effective pip counts (EPC).
+/// Gammons are ignored, so it is only meant for races in which both sides have borne off
+/// or will soon bear off a checker.
+#[derive(Default)]
+pub struct RaceEvaluator {
+    bearoff: Option<OneSidedBearoff>,
+}
+
+impl<G: State> PartialEvaluator<G> for RaceEvaluator {
+    fn try_eval(&self, pos: &G) -> f32 {
+        self.eval(pos).equity()
+    }
+}
+
+impl<G: State> Evaluator<G> for RaceEvaluator {
+    fn eval(&self, pos: &G) -> Probabilities {
+        let counts = self.counts(pos);
+        let win = Self::win_probability(counts.effective, counts.opp_effective);
+        Probabilities {
+            win_n: win,
+            win_g: 0.0,
+            win_b: 0.0,
+            lose_n: 1.0 - win,
+            lose_g: 0.0,
+            lose_b: 0.0,
+        }
+    }
+}
+
+impl RaceEvaluator {
+    pub fn new() -> Self {
+        Self { bearoff: None }
+    }
+
+    /// Uses the expected number of rolls of the one-sided bearoff database for the
+    /// effective pip count of a side with all checkers home.
+    pub fn with_bearoff(mut self, bearoff: OneSidedBearoff) -> Self {
+        self.bearoff = Some(bearoff);
+        self
+    }
+
+    fn effective(&self, side: &Side) -> f32 {
+        if let (Some(bearoff), Some(board)) = (&self.bearoff, home_board(side)) {
+            if board.iter().sum::<u8>() <= bearoff.max_checkers() {
+                return bearoff.mean_rolls(&board) * PIPS_PER_ROLL;
+            }
+        }
+        Self::estimated_effective(side)
+    }
+
+    /// Pip count plus an estimate of the wastage, based on the penalties of the Keith count.
+    fn estimated_effective(side: &Side) -> f32 {
+        match pips(side) {
+            0 => 0.0,
+            _ => keith(side) + BASE_WASTAGE,
+        }
+    }
+
+    /// Effective pip count of a home board, estimated without a bearoff database.
+    pub fn estimated_board_effective(board: &Board) -> f32 {
+        let mut side = [0; 26];
+        side[1..7].copy_from_slice(board);
+        Self::estimated_effective(&side)
+    }
+
+    pub fn counts<G: State>(&self, pos: &G) -> RaceCounts {
+        let (x, o) = sides(pos);
+        RaceCounts {
+            pips: pips(&x),
+            opp_pips: pips(&o),
+            effective: self.effective(&x),
+            opp_effective: self.effective(&o),
+            keith: keith(&x),
+            opp_keith: keith(&o),
+            thorp: thorp(&x),
+            opp_thorp: thorp(&o),
+        }
+    }
+
+    /// Probability of the player on roll winning a race of effective pip counts
+    /// `effective` against `opp_effective`. The number of rolls each side needs is
+    /// approximated by a normal distribution, the player on roll winning ties.
+    pub fn win_probability(effective: f32, opp_effective: f32) -> f32 {
+        if effective <= 0.0 {
+            return 1.0;
+        }
+        let rolls = effective / PIPS_PER_ROLL;
+        let opp_rolls = opp_effective / PIPS_PER_ROLL;
+        let variance = (effective + opp_effective) * VARIANCE_PER_PIP;
+        normal_cdf((opp_rolls - rolls + 0.5) / variance.sqrt().max(f32::EPSILON))
+    }
+
+    /// Cube action of the player on roll according to a race formula.
+    /// `redouble` is whether the player on roll owns the cube.
+    pub fn cube_action<G: State>(
+        &self,
+        pos: &G,
+        formula: RaceFormula,
+        redouble: bool,
+    ) -> CubeAction {
+        let counts = self.counts(pos);
+        if counts.pips == 0 {
+            // the player on roll has already borne off all checkers
+            return CubeAction::NoDouble;
+        }
+        // (lead, doubling point, redoubling point, take point), a larger lead being better
+        let (lead, double, redouble_point, take) = match formula {
+            RaceFormula::Keith => {
+                let keith = counts.keith * 8.0 / 7.0;
+                (counts.opp_keith - keith, -4.0, -3.0, -2.0)
+            }
+            RaceFormula::Thorp => {
+                let thorp = if counts.thorp > 30.0 {
+                    counts.thorp * 1.1
+                } else {
+                    counts.thorp
+                };
+                (counts.opp_thorp - thorp, -2.0, -1.0, 2.0)
+            }
+            RaceFormula::EightNineTwelve => {
+                let lead = (counts.opp_pips as f32 - counts.pips as f32) / counts.pips as f32;
+                (100.0 * lead, 8.0, 9.0, 12.0)
+            }
+        };
+        let threshold = if redouble { redouble_point } else { double };
+        // Thorp passes at the take point already, the other formulas only beyond it
+        let pass = match formula {
+            RaceFormula::Thorp => lead >= take,
+            _ => lead > take,
+        };
+        if lead < threshold {
+            CubeAction::NoDouble
+        } else if pass {
+            CubeAction::DoublePass
+        } else {
+            CubeAction::DoubleTake
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cube::CubeAction;
+    use crate::evaluator::{RaceEvaluator, RaceFormula};
+    use bkgm::pos;
+
+    #[test]
+    fn win_probability() {
+        // the player on roll is half a roll ahead
+        assert!((RaceEvaluator::win_probability(80.0, 80.0) - 0.616).abs() < 0.005);
+        assert!(RaceEvaluator::win_probability(50.0, 80.0) > 0.9);
+        assert!(RaceEvaluator::win_probability(80.0, 50.0) < 0.1);
+        assert_eq!(RaceEvaluator::win_probability(0.0, 10.0), 1.0);
+    }
+
+    #[test]
+    fn thorp_passes_at_take_point() {
+        // Thorp counts of 15 against 17
+        let pos = pos!(x 6:2; o 19:1, 17:1);
+        let race = RaceEvaluator::new();
+        assert_eq!(
+            race.cube_action(&pos, RaceFormula::Thorp, false),
+            CubeAction::DoublePass
+        );
+    }
+
+    #[test]
+    fn finished_race_is_no_double() {
+        // all checkers of the player on roll are borne off
+        let pos = pos!(x 1:0; o 19:1);
+        let race = RaceEvaluator::new();
+        assert_eq!(
+            race.cube_action(&pos, RaceFormula::EightNineTwelve, false),
+            CubeAction::NoDouble
+        );
+    }
+}
@@ -1,16 +1,86 @@
 use std::marker::PhantomData;
+use std::ops::Range;
 
-use crate::probabilities::{Probabilities, ResultCounter};
+use crate::dice_gen::StratifiedDice;
+use crate::probabilities::{Probabilities, ProbabilitiesSum};
+use bkgm::dice::ALL_21;
 use bkgm::dice_gen::{DiceGen, FastrandDice};
 use bkgm::position::GameState::{GameOver, Ongoing};
-use bkgm::{GameResult, State};
+use bkgm::{Dice, State};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 
 use super::{Evaluator, PartialEvaluator};
 
+/// Estimate of a rollout together with its standard errors.
+#[derive(Clone, Copy, Debug)]
+pub struct RolloutEstimate {
+    pub probs: Probabilities,
+    /// Standard error of every outcome in `probs`.
+    pub std_errors: Probabilities,
+    pub equity: f32,
+    pub equity_std_error: f32,
+}
+
+impl RolloutEstimate {
+    fn from_sum(sum: &ProbabilitiesSum) -> Self {
+        let probs = sum.probabilities();
+        Self {
+            probs,
+            std_errors: sum.std_errors(),
+            equity: probs.equity(),
+            equity_std_error: sum.equity_std_error(),
+        }
+    }
+
+    /// 95% confidence interval of the equity.
+    pub fn confidence_interval(&self) -> (f32, f32) {
+        let margin = 1.96 * self.equity_std_error;
+        (self.equity - margin, self.equity + margin)
+    }
+}
+
+/// Outcome of a rollout.
+#[derive(Clone, Copy, Debug)]
+pub struct RolloutResult {
+    /// Plain Monte Carlo estimate.
+    pub raw: RolloutEstimate,
+    /// Estimate with the luck of every roll removed, if variance reduction is enabled.
+    pub reduced: Option<RolloutEstimate>,
+    pub trials: usize,
+}
+
+impl RolloutResult {
+    /// The variance reduced estimate if available, the raw one otherwise.
+    pub fn estimate(&self) -> &RolloutEstimate {
+        self.reduced.as_ref().unwrap_or(&self.raw)
+    }
+
+    pub fn probabilities(&self) -> Probabilities {
+        self.estimate().probs
+    }
+}
+
+/// Number of trials played between two checks of the stopping rule.
+const BATCH_SIZE: usize = 144;
+const DEFAULT_SEED: u64 = 0x5eed;
+
+/// Seed of a single trial, derived from the master seed with SplitMix64.
+fn trial_seed(seed: u64, trial: usize) -> u64 {
+    let mut z = seed.wrapping_add((trial as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
 pub struct RolloutEvaluator<G: State, E: Evaluator<G>> {
     phantom: PhantomData<G>,
     evaluator: E,
     num_rollouts: usize,
+    variance_reduction: bool,
+    truncation: Option<usize>,
+    seed: u64,
+    target_width: Option<f32>,
+    stratification: usize,
 }
 
 impl<G: State, E: Evaluator<G>> PartialEvaluator<G> for RolloutEvaluator<G, E> {
@@ -19,9 +89,36 @@ impl<G: State, E: Evaluator<G>> PartialEvaluator<G> for RolloutEvaluator<G, E> {
     }
 }
 
+/// Plays the trials on the current thread, as evaluators aren't required to be `Sync`.
+/// Use `parallel` to play them in parallel, which gives the same result.
 impl<G: State, E: Evaluator<G>> Evaluator<G> for RolloutEvaluator<G, E> {
     fn eval(&self, pos: &G) -> Probabilities {
-        self.rollout(pos)
+        self.rollout_sequential(pos).probabilities()
+    }
+}
+
+/// Rollout evaluator playing the trials in parallel, created with `RolloutEvaluator::parallel`.
+pub struct ParallelRolloutEvaluator<G: State, E: Evaluator<G>> {
+    rollout: RolloutEvaluator<G, E>,
+}
+
+impl<G: State + Sync, E: Evaluator<G> + Sync> PartialEvaluator<G>
+    for ParallelRolloutEvaluator<G, E>
+{
+    fn try_eval(&self, pos: &G) -> f32 {
+        self.eval(pos).equity()
+    }
+}
+
+impl<G: State + Sync, E: Evaluator<G> + Sync> Evaluator<G> for ParallelRolloutEvaluator<G, E> {
+    fn eval(&self, pos: &G) -> Probabilities {
+        self.rollout.rollout(pos).probabilities()
+    }
+}
+
+impl<G: State, E: Evaluator<G>> ParallelRolloutEvaluator<G, E> {
+    pub fn rollout(&self) -> &RolloutEvaluator<G, E> {
+        &self.rollout
     }
 }
 
@@ -31,37 +128,216 @@ impl<G: State, E: Evaluator<G>> RolloutEvaluator<G, E> {
             phantom: PhantomData,
             evaluator,
             num_rollouts,
+            variance_reduction: false,
+            truncation: None,
+            seed: DEFAULT_SEED,
+            target_width: None,
+            stratification: 0,
         }
     }
 
-    fn rollout(&self, pos: &G) -> Probabilities {
-        let mut dice_gen = FastrandDice::new();
-        let mut counter = ResultCounter::default();
-        for _ in 0..self.num_rollouts {
-            let result = self.single_rollout(&mut dice_gen, pos);
-            counter.add(result);
+    /// Stratifies the first `depth` rolls (at most two) of every trial across all 36 rolls.
+    pub fn with_stratification(mut self, depth: usize) -> Self {
+        assert!(depth <= 2, "only the first two rolls can be stratified");
+        self.stratification = depth;
+        self
+    }
+
+    /// Stops the rollout as soon as the 95% confidence interval of the equity is narrower
+    /// than `target_width`. `num_rollouts` remains the maximum number of trials.
+    pub fn with_target_width(mut self, target_width: f32) -> Self {
+        self.target_width = Some(target_width);
+        self
+    }
+
+    /// Master seed from which the dice of every trial are derived.
+    /// The same seed and settings always lead to the same result, regardless of the number of threads.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Stops every game after `half_moves` moves and uses the evaluation of the reached position
+    /// instead of playing the game to the end.
+    pub fn with_truncation(mut self, half_moves: usize) -> Self {
+        self.truncation = Some(half_moves);
+        self
+    }
+
+    /// Subtracts the luck of every roll from the game results.
+    /// Needs far fewer trials, but evaluates all 21 rolls at every move.
+    pub fn with_variance_reduction(mut self, variance_reduction: bool) -> Self {
+        self.variance_reduction = variance_reduction;
+        self
+    }
+
+    /// Evaluates positions with `rollout` instead of `rollout_sequential`.
+    pub fn parallel(self) -> ParallelRolloutEvaluator<G, E> {
+        ParallelRolloutEvaluator { rollout: self }
+    }
+
+    /// Plays the trials in parallel. Results are summed in trial order afterwards,
+    /// so that floating point rounding doesn't depend on the scheduling of the threads.
+    /// With a target width the trials are played in batches, checking the confidence interval after each.
+    pub fn rollout(&self, pos: &G) -> RolloutResult
+    where
+        G: Sync,
+        E: Sync,
+    {
+        self.rollout_batches(|trials| {
+            trials
+                .into_par_iter()
+                .map(|trial| self.trial(pos, trial))
+                .collect()
+        })
+    }
+
+    /// Like `rollout`, but plays all trials on the current thread.
+    pub fn rollout_sequential(&self, pos: &G) -> RolloutResult {
+        self.rollout_batches(|trials| trials.map(|trial| self.trial(pos, trial)).collect())
+    }
+
+    /// Plays the trial with index `trial`, with dice derived from the master seed.
+    fn trial(&self, pos: &G, trial: usize) -> (Probabilities, Probabilities) {
+        let mut dice_gen = StratifiedDice::new(
+            FastrandDice::with_seed(trial_seed(self.seed, trial)),
+            trial,
+            self.stratification,
+        );
+        self.single_rollout(&mut dice_gen, pos)
+    }
+
+    /// Sums the results of the trials played by `play`, batch by batch.
+    fn rollout_batches<F>(&self, play: F) -> RolloutResult
+    where
+        F: Fn(Range<usize>) -> Vec<(Probabilities, Probabilities)>,
+    {
+        let mut raw = ProbabilitiesSum::default();
+        let mut reduced = ProbabilitiesSum::default();
+        let mut trials = 0;
+        while trials < self.num_rollouts {
+            let batch = match self.target_width {
+                Some(_) => BATCH_SIZE.min(self.num_rollouts - trials),
+                None => self.num_rollouts - trials,
+            };
+            for (result, luck) in play(trials..trials + batch) {
+                raw.add(&result);
+                reduced.add(&(result - luck));
+            }
+            trials += batch;
+
+            if let Some(target_width) = self.target_width {
+                let sum = if self.variance_reduction {
+                    &reduced
+                } else {
+                    &raw
+                };
+                let (low, high) = RolloutEstimate::from_sum(sum).confidence_interval();
+                if high - low < target_width {
+                    break;
+                }
+            }
+        }
+
+        RolloutResult {
+            raw: RolloutEstimate::from_sum(&raw),
+            reduced: self
+                .variance_reduction
+                .then(|| RolloutEstimate::from_sum(&reduced)),
+            trials,
         }
-        counter.probabilities()
     }
 
-    fn single_rollout<V: DiceGen>(&self, dice_gen: &mut V, pos: &G) -> GameResult {
+    /// Plays a single game and returns its result together with the summed luck of all rolls,
+    /// both from the perspective of the player on roll in `pos`.
+    /// A truncated game results in the evaluation of the position it was stopped in.
+    fn single_rollout<V: DiceGen>(
+        &self,
+        dice_gen: &mut V,
+        pos: &G,
+    ) -> (Probabilities, Probabilities) {
         let mut pos = pos.clone();
         let mut depth = 0;
+        let mut luck = Probabilities::empty();
         loop {
             match pos.game_state() {
+                Ongoing if self.truncation == Some(depth) => {
+                    let probs = self.evaluator.eval(&pos);
+                    let probs = if depth % 2 == 0 { probs } else { probs.flip() };
+                    return (probs, luck);
+                }
                 Ongoing => {
                     let dice = dice_gen.roll();
+                    if self.variance_reduction {
+                        let roll_luck = self.luck(&pos, &dice);
+                        luck = if depth % 2 == 0 {
+                            luck + roll_luck
+                        } else {
+                            luck + roll_luck.flip()
+                        };
+                    }
                     pos = self.evaluator.best_position(&pos, &dice);
                     depth += 1;
                 }
                 GameOver(result) => {
-                    return if depth % 2 == 0 {
+                    let result = if depth % 2 == 0 {
                         result
                     } else {
                         result.reverse()
                     };
+                    return (Probabilities::from_result(&result), luck);
                 }
             }
         }
     }
+
+    /// Evaluation after the best move for `dice` minus the average over all rolls,
+    /// from the perspective of the player on roll.
+    fn luck(&self, pos: &G, dice: &Dice) -> Probabilities {
+        let mut expected = Probabilities::empty();
+        for (roll, n) in ALL_21 {
+            expected = expected + self.after_roll(pos, &roll) * (n as f32 / 36.0);
+        }
+        self.after_roll(pos, dice) - expected
+    }
+
+    fn after_roll(&self, pos: &G, dice: &Dice) -> Probabilities {
+        let child = self.evaluator.best_position(pos, dice);
+        let probs = match child.game_state() {
+            Ongoing => self.evaluator.eval(&child),
+            GameOver(result) => Probabilities::from_result(&result),
+        };
+        probs.flip()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bearoff::TwoSidedBearoff;
+    use crate::evaluator::{Evaluator, RolloutEvaluator};
+    use bkgm::pos;
+
+    #[test]
+    fn parallel_matches_sequential() {
+        let bearoff = TwoSidedBearoff::generate(2);
+        let pos = pos!(x 6:1, 5:1; o 19:1, 20:1);
+        let rollout = RolloutEvaluator::new(&bearoff, 500)
+            .with_seed(7)
+            .with_variance_reduction(true);
+        let parallel = rollout.rollout(&pos);
+        let sequential = rollout.rollout_sequential(&pos);
+        assert_eq!(parallel.trials, sequential.trials);
+        assert_eq!(parallel.raw.probs, sequential.raw.probs);
+        assert_eq!(
+            parallel.raw.equity.to_bits(),
+            sequential.raw.equity.to_bits()
+        );
+        assert_eq!(
+            parallel.estimate().equity.to_bits(),
+            sequential.estimate().equity.to_bits()
+        );
+
+        let probs = rollout.eval(&pos);
+        assert_eq!(rollout.parallel().eval(&pos), probs);
+    }
 }
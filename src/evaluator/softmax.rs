@@ -0,0 +1,212 @@
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::probabilities::Probabilities;
+use bkgm::State;
+
+use super::{Evaluator, PartialEvaluator};
+
+/// Temperature as a function of a step, e.g. the training episode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TemperatureSchedule {
+    Constant(f32),
+    /// From `start` to `end` in `steps` steps, `end` afterwards.
+    Linear {
+        start: f32,
+        end: f32,
+        steps: usize,
+    },
+    /// `start * decay^step`, but never below `min`.
+    Exponential {
+        start: f32,
+        decay: f32,
+        min: f32,
+    },
+}
+
+impl TemperatureSchedule {
+    pub fn temperature(&self, step: usize) -> f32 {
+        match *self {
+            TemperatureSchedule::Constant(temperature) => temperature,
+            TemperatureSchedule::Linear { start, end, steps } => {
+                if step >= steps {
+                    end
+                } else {
+                    start + (end - start) * step as f32 / steps as f32
+                }
+            }
+            TemperatureSchedule::Exponential { start, decay, min } => {
+                (start * decay.powi(step.min(i32::MAX as usize) as i32)).max(min)
+            }
+        }
+    }
+}
+
+/// Picks moves with probability proportional to exp(value / temperature), the value being
+/// the evaluation of the inner evaluator for the player making the move.
+/// The temperature is relative to the scale of the inner evaluator, and a temperature of
+/// zero picks the best move.
+pub struct SoftmaxEvaluator<G: State, E: PartialEvaluator<G>> {
+    phantom: PhantomData<G>,
+    evaluator: E,
+    schedule: TemperatureSchedule,
+    step: AtomicUsize,
+}
+
+impl<G: State, E: PartialEvaluator<G>> PartialEvaluator<G> for SoftmaxEvaluator<G, E> {
+    fn try_eval(&self, pos: &G) -> f32 {
+        self.evaluator.try_eval(pos)
+    }
+
+    fn try_eval_batch(&self, positions: &[G]) -> Vec<f32> {
+        self.evaluator.try_eval_batch(positions)
+    }
+
+    fn best_position(&self, pos: &G, dice: &bkgm::Dice) -> G {
+        let candidates = self.move_probabilities(pos, dice);
+        let mut sample = fastrand::f32();
+        for (position, probability) in &candidates {
+            if sample < *probability {
+                return *position;
+            }
+            sample -= probability;
+        }
+        // Rounding errors can leave a tiny remainder
+        candidates.last().unwrap().0
+    }
+}
+
+impl<G: State, E: Evaluator<G>> Evaluator<G> for SoftmaxEvaluator<G, E> {
+    fn eval(&self, pos: &G) -> Probabilities {
+        self.evaluator.eval(pos)
+    }
+
+    fn eval_batch(&self, positions: &[G]) -> Vec<Probabilities> {
+        self.evaluator.eval_batch(positions)
+    }
+}
+
+impl<G: State, E: PartialEvaluator<G>> SoftmaxEvaluator<G, E> {
+    pub fn new(evaluator: E, schedule: TemperatureSchedule) -> Self {
+        Self {
+            phantom: PhantomData,
+            evaluator,
+            schedule,
+            step: AtomicUsize::new(0),
+        }
+    }
+
+    /// Step of the schedule to start at.
+    pub fn with_step(self, step: usize) -> Self {
+        self.set_step(step);
+        self
+    }
+
+    /// The step isn't advanced by picking moves, so the caller decides what a step is.
+    pub fn set_step(&self, step: usize) {
+        self.step.store(step, Ordering::Relaxed);
+    }
+
+    pub fn step(&self) -> usize {
+        self.step.load(Ordering::Relaxed)
+    }
+
+    pub fn temperature(&self) -> f32 {
+        self.schedule.temperature(self.step())
+    }
+
+    /// Every legal move of `pos` with the probability of it being picked.
+    pub fn move_probabilities(&self, pos: &G, dice: &bkgm::Dice) -> Vec<(G, f32)> {
+        let positions = pos.possible_positions(dice);
+        // Children are evaluated for the opponent, who is on roll there
+        let values: Vec<f32> = self
+            .evaluator
+            .try_eval_batch(&positions)
+            .iter()
+            .map(|value| -value)
+            .collect();
+        let temperature = self.temperature();
+        let best = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+
+        let weights: Vec<f32> = if temperature <= 0.0 {
+            // Ties share the probability
+            values
+                .iter()
+                .map(|value| if *value == best { 1.0 } else { 0.0 })
+                .collect()
+        } else {
+            values
+                .iter()
+                .map(|value| ((value - best) / temperature).exp())
+                .collect()
+        };
+        let sum: f32 = weights.iter().sum();
+        positions
+            .into_iter()
+            .zip(weights)
+            .map(|(position, weight)| (position, weight / sum))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{PartialEvaluator, PubEval, SoftmaxEvaluator, TemperatureSchedule};
+    use bkgm::{Dice, BACKGAMMON};
+
+    #[test]
+    fn schedules() {
+        assert_eq!(TemperatureSchedule::Constant(0.5).temperature(1000), 0.5);
+
+        let linear = TemperatureSchedule::Linear {
+            start: 1.0,
+            end: 0.0,
+            steps: 100,
+        };
+        assert_eq!(linear.temperature(0), 1.0);
+        assert!((linear.temperature(25) - 0.75).abs() < 1e-6);
+        assert_eq!(linear.temperature(200), 0.0);
+
+        let exponential = TemperatureSchedule::Exponential {
+            start: 1.0,
+            decay: 0.5,
+            min: 0.1,
+        };
+        assert_eq!(exponential.temperature(1), 0.5);
+        assert_eq!(exponential.temperature(10), 0.1);
+    }
+
+    #[test]
+    fn probabilities_follow_softmax() {
+        let dice = Dice::new(3, 1);
+        let softmax = SoftmaxEvaluator::new(PubEval::new(), TemperatureSchedule::Constant(0.5));
+        let candidates = softmax.move_probabilities(&BACKGAMMON, &dice);
+        let values: Vec<f32> = candidates
+            .iter()
+            .map(|(position, _)| -PubEval::new().try_eval(position))
+            .collect();
+        let sum: f32 = values.iter().map(|value| (value / 0.5).exp()).sum();
+        for ((_, probability), value) in candidates.iter().zip(values) {
+            assert!((probability - (value / 0.5).exp() / sum).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn zero_temperature_picks_best_move() {
+        let dice = Dice::new(6, 4);
+        let softmax = SoftmaxEvaluator::new(PubEval::new(), TemperatureSchedule::Constant(0.0));
+        let best = PubEval::new().best_position(&BACKGAMMON, &dice);
+        for _ in 0..10 {
+            assert_eq!(softmax.best_position(&BACKGAMMON, &dice), best);
+        }
+        let nearly_zero =
+            SoftmaxEvaluator::new(PubEval::new(), TemperatureSchedule::Constant(1e-4));
+        let candidates = nearly_zero.move_probabilities(&BACKGAMMON, &dice);
+        let probability = candidates
+            .iter()
+            .find(|(position, _)| *position == best)
+            .unwrap()
+            .1;
+        assert!(probability > 0.999);
+    }
+}
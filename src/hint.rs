@@ -0,0 +1,251 @@
+use crate::{evaluator::Evaluator, probabilities::Probabilities};
+use bkgm::{Dice, GameState::GameOver, State};
+
+/// Checkers moved from the bar start at 25, checkers borne off end at 0.
+const BAR: usize = 25;
+const OFF: usize = 0;
+
+/// A legal move of a position with its evaluation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveHint<G: State> {
+    /// Position after the move, from the perspective of the opponent who is now on roll.
+    pub position: G,
+    /// From the perspective of the player making the move.
+    pub probabilities: Probabilities,
+    pub equity: f32,
+    /// Equity lost compared to the best move, 0.0 for the best move.
+    pub equity_loss: f32,
+    pub notation: String,
+}
+
+/// All legal moves of `pos` for `dice`, best move first.
+pub fn hint<G: State, E: Evaluator<G>>(evaluator: &E, pos: &G, dice: &Dice) -> Vec<MoveHint<G>> {
+    let positions = pos.possible_positions(dice);
+    let probs = evaluator.eval_batch(&positions);
+    let mut hints: Vec<MoveHint<G>> = positions
+        .into_iter()
+        .zip(probs)
+        .map(|(position, probs)| {
+            let probs = match position.game_state() {
+                GameOver(result) => Probabilities::from_result(&result),
+                _ => probs,
+            };
+            let probabilities = probs.flip();
+            MoveHint {
+                position,
+                probabilities,
+                equity: probabilities.equity(),
+                equity_loss: 0.0,
+                notation: notation(pos, &position, dice),
+            }
+        })
+        .collect();
+    hints.sort_by(|a, b| b.equity.partial_cmp(&a.equity).unwrap());
+    if let Some(best) = hints.first().map(|hint| hint.equity) {
+        for hint in hints.iter_mut() {
+            hint.equity_loss = best - hint.equity;
+        }
+    }
+    hints
+}
+
+/// Checkers of the player on roll by point, including the bar and the checkers borne off.
+fn checkers<G: State>(pos: &G) -> [u8; 26] {
+    let mut checkers = [0; 26];
+    checkers[BAR] = pos.x_bar();
+    checkers[OFF] = pos.x_off();
+    for (i, checker) in checkers.iter_mut().enumerate().take(BAR).skip(1) {
+        *checker = pos.pip(i).max(0) as u8;
+    }
+    checkers
+}
+
+fn point_name(point: usize) -> String {
+    match point {
+        BAR => "bar".to_string(),
+        OFF => "off".to_string(),
+        point => point.to_string(),
+    }
+}
+
+/// Checkers of both sides by point, from the perspective of the player on roll.
+/// The opponent's checkers on the bar are kept at `OFF`, where they enter from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Board {
+    x: [u8; 26],
+    o: [u8; 26],
+}
+
+impl Board {
+    fn new<G: State>(pos: &G) -> Self {
+        let mut o = [0; 26];
+        o[OFF] = pos.o_bar();
+        for (i, checker) in o.iter_mut().enumerate().take(BAR).skip(1) {
+            *checker = (-pos.pip(i)).max(0) as u8;
+        }
+        Self {
+            x: checkers(pos),
+            o,
+        }
+    }
+
+    /// Moves a checker from `from` by `die` pips, returning the new board, the point
+    /// the checker reaches and whether it hits. `None` if the rules don't allow it.
+    fn play(&self, from: usize, die: usize) -> Option<(Board, usize, bool)> {
+        if from == OFF || self.x[from] == 0 || (self.x[BAR] > 0 && from != BAR) {
+            return None;
+        }
+        let mut next = *self;
+        next.x[from] -= 1;
+        if from <= die {
+            // Bearing off needs all checkers home, a higher die only moves the highest checker
+            let home = self.x[7..].iter().all(|n| *n == 0);
+            let highest = self.x[from + 1..=6].iter().all(|n| *n == 0);
+            if !home || (from < die && !highest) {
+                return None;
+            }
+            next.x[OFF] += 1;
+            return Some((next, OFF, false));
+        }
+        let to = from - die;
+        let hit = match self.o[to] {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        if hit {
+            next.o[to] = 0;
+            next.o[OFF] += 1;
+        }
+        next.x[to] += 1;
+        Some((next, to, hit))
+    }
+}
+
+/// Die values of a roll, four of them for doubles.
+fn die_values(dice: &Dice) -> Vec<usize> {
+    for big in 1..=6 {
+        for small in 1..=big {
+            if Dice::new(big, small) == *dice {
+                return match big == small {
+                    true => vec![big; 4],
+                    false => vec![big, small],
+                };
+            }
+        }
+    }
+    unreachable!("dice are always between 1 and 6")
+}
+
+/// Single checker moves `(from, to, hit)`, each playing one of `dice`, that turn `board` into `target`.
+fn find_moves(board: &Board, target: &Board, dice: &[usize]) -> Option<Vec<(usize, usize, bool)>> {
+    if board == target {
+        return Some(Vec::new());
+    }
+    for (i, die) in dice.iter().enumerate() {
+        if dice[..i].contains(die) {
+            continue;
+        }
+        let mut rest = dice.to_vec();
+        rest.remove(i);
+        for from in (1..=BAR).rev() {
+            if let Some((next, to, hit)) = board.play(from, *die) {
+                if let Some(mut moves) = find_moves(&next, target, &rest) {
+                    moves.insert(0, (from, to, hit));
+                    return Some(moves);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Standard notation of the move from `before` to `after` with `dice`, e.g. "8/5* 6/5",
+/// "13/11(2)" or "13/7*/6". The move is reconstructed by searching for a sequence of dice
+/// that leads from one position to the other. Moves of a single checker are combined,
+/// intermediate points are only shown if a checker was hit there.
+pub fn notation<G: State>(before: &G, after: &G, dice: &Dice) -> String {
+    let start = Board::new(before);
+    let target = Board::new(&after.flip());
+    let moves = match find_moves(&start, &target, &die_values(dice)) {
+        Some(moves) if !moves.is_empty() => moves,
+        _ => return "Cannot move".to_string(),
+    };
+
+    // Points touched by every moved checker, together with whether it hit there
+    let mut paths: Vec<Vec<(usize, bool)>> = Vec::new();
+    for (from, to, hit) in moves {
+        match paths.iter_mut().find(|path| path.last().unwrap().0 == from) {
+            Some(path) => path.push((to, hit)),
+            None => paths.push(vec![(from, false), (to, hit)]),
+        }
+    }
+    paths.sort_by(|a, b| b.cmp(a));
+
+    let mut parts: Vec<(String, usize)> = Vec::new();
+    for path in paths {
+        let last = path.len() - 1;
+        let part = path
+            .iter()
+            .enumerate()
+            .filter(|(i, (_, hit))| *i == 0 || *i == last || *hit)
+            .map(|(_, (point, hit))| match hit {
+                true => format!("{}*", point_name(*point)),
+                false => point_name(*point),
+            })
+            .collect::<Vec<String>>()
+            .join("/");
+        match parts.last_mut() {
+            Some((previous, count)) if *previous == part => *count += 1,
+            _ => parts.push((part, 1)),
+        }
+    }
+    parts
+        .into_iter()
+        .map(|(part, count)| match count {
+            1 => part,
+            count => format!("{}({})", part, count),
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hint::notation;
+    use bkgm::{pos, Dice, State, O_BAR, X_BAR};
+
+    #[test]
+    fn move_notation() {
+        let before = pos!(x 24:2, 13:1; o 20:1);
+        let after = pos!(x 20:1, 18:1, 13:1; o O_BAR:1).flip();
+        assert_eq!(notation(&before, &after, &Dice::new(6, 4)), "24/20* 24/18");
+
+        let before = pos!(x 8:1, 6:1; o 19:2);
+        let after = pos!(x 5:1, 2:1; o 19:2).flip();
+        assert_eq!(notation(&before, &after, &Dice::new(6, 1)), "8/2 6/5");
+
+        let before = pos!(x 13:2, 6:2; o 19:2);
+        let after = pos!(x 11:2, 4:2; o 19:2).flip();
+        assert_eq!(
+            notation(&before, &after, &Dice::new(2, 2)),
+            "13/11(2) 6/4(2)"
+        );
+
+        let before = pos!(x X_BAR:1, 6:1; o 22:2);
+        let after = pos!(x 21:1, 4:1; o 22:2).flip();
+        assert_eq!(notation(&before, &after, &Dice::new(4, 2)), "bar/21 6/4");
+    }
+
+    #[test]
+    fn combined_moves() {
+        let before = pos!(x 24:2; o 19:2);
+        let after = pos!(x 16:2; o 19:2).flip();
+        assert_eq!(notation(&before, &after, &Dice::new(4, 4)), "24/16(2)");
+
+        // the 12-point is blocked, so the checker hits on the 7-point on the way
+        let before = pos!(x 13:1; o 12:2, 7:1);
+        let after = pos!(x 6:1; o 12:2, O_BAR:1).flip();
+        assert_eq!(notation(&before, &after, &Dice::new(6, 1)), "13/7*/6");
+    }
+}
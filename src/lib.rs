@@ -1,6 +1,12 @@
+pub mod analysis;
+pub mod bearoff;
+pub mod cube;
+pub mod dice_gen;
 pub mod duel;
 pub mod evaluator;
+pub mod hint;
 pub mod inputs;
+pub mod matchplay;
 pub mod model;
 pub mod probabilities;
 pub mod training;
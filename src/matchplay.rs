@@ -0,0 +1,404 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use crate::probabilities::Probabilities;
+
+/// Score of a match, seen from the player on roll.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct MatchState {
+    pub length: u32,
+    pub score: u32,
+    pub opp_score: u32,
+    /// The current game is the Crawford game, the cube may not be used.
+    pub crawford: bool,
+}
+
+impl MatchState {
+    pub fn new(length: u32, score: u32, opp_score: u32, crawford: bool) -> Self {
+        assert!(
+            score < length && opp_score < length,
+            "match is already over"
+        );
+        Self {
+            length,
+            score,
+            opp_score,
+            crawford,
+        }
+    }
+
+    pub fn away(&self) -> u32 {
+        self.length - self.score
+    }
+
+    pub fn opp_away(&self) -> u32 {
+        self.length - self.opp_score
+    }
+
+    /// Neither player is 1-away, the Crawford game is still to come.
+    pub fn is_pre_crawford(&self) -> bool {
+        self.away() > 1 && self.opp_away() > 1
+    }
+
+    /// The Crawford game has been played and one player is 1-away.
+    pub fn is_post_crawford(&self) -> bool {
+        !self.crawford && !self.is_pre_crawford() && (self.away() > 1 || self.opp_away() > 1)
+    }
+
+    /// Match state as seen by the opponent.
+    pub fn flip(&self) -> Self {
+        Self {
+            score: self.opp_score,
+            opp_score: self.score,
+            ..*self
+        }
+    }
+}
+
+/// Kazaross XG2 match equity table in percent, `KAZAROSS_XG2_PRE[i][j]` being the MWC of a player
+/// `i + 1`-away against an opponent `j + 1`-away before or in the Crawford game.
+const KAZAROSS_XG2_PRE: [[f32; 15]; 15] = [
+    [
+        50.0, 68.0, 75.2, 81.5, 84.1, 88.9, 90.6, 93.3, 94.4, 96.0, 96.7, 97.7, 98.1, 98.7, 98.9,
+    ],
+    [
+        32.0, 50.0, 60.0, 66.9, 74.4, 80.0, 84.2, 87.9, 90.6, 92.9, 94.5, 95.8, 96.8, 97.6, 98.2,
+    ],
+    [
+        24.8, 40.0, 50.0, 57.1, 64.7, 71.0, 76.2, 80.8, 84.5, 87.6, 90.2, 92.3, 94.0, 95.3, 96.4,
+    ],
+    [
+        18.5, 33.1, 42.9, 50.0, 57.6, 64.1, 69.8, 74.9, 79.3, 83.0, 86.2, 88.9, 91.1, 92.9, 94.4,
+    ],
+    [
+        15.9, 25.6, 35.3, 42.4, 50.0, 56.7, 62.9, 68.5, 73.5, 77.9, 81.7, 85.0, 87.8, 90.1, 92.1,
+    ],
+    [
+        11.1, 20.0, 29.0, 35.9, 43.3, 50.0, 56.3, 62.2, 67.6, 72.4, 76.7, 80.5, 83.8, 86.6, 89.0,
+    ],
+    [
+        9.4, 15.8, 23.8, 30.2, 37.1, 43.7, 50.0, 56.0, 61.6, 66.7, 71.3, 75.5, 79.2, 82.4, 85.3,
+    ],
+    [
+        6.7, 12.1, 19.2, 25.1, 31.5, 37.8, 44.0, 50.0, 55.7, 61.0, 65.9, 70.3, 74.4, 78.0, 81.2,
+    ],
+    [
+        5.6, 9.4, 15.5, 20.7, 26.5, 32.4, 38.4, 44.3, 50.0, 55.4, 60.5, 65.2, 69.6, 73.5, 77.1,
+    ],
+    [
+        4.0, 7.1, 12.4, 17.0, 22.1, 27.6, 33.3, 39.0, 44.6, 50.0, 55.2, 60.1, 64.6, 68.8, 72.6,
+    ],
+    [
+        3.3, 5.5, 9.8, 13.8, 18.3, 23.3, 28.7, 34.1, 39.5, 44.8, 50.0, 55.0, 59.7, 64.1, 68.2,
+    ],
+    [
+        2.3, 4.2, 7.7, 11.1, 15.0, 19.5, 24.5, 29.7, 34.8, 39.9, 45.0, 50.0, 54.8, 59.3, 63.6,
+    ],
+    [
+        1.9, 3.2, 6.0, 8.9, 12.2, 16.2, 20.8, 25.6, 30.4, 35.4, 40.3, 45.2, 50.0, 54.6, 59.0,
+    ],
+    [
+        1.3, 2.4, 4.7, 7.1, 9.9, 13.4, 17.6, 22.0, 26.5, 31.2, 35.9, 40.7, 45.4, 50.0, 54.5,
+    ],
+    [
+        1.1, 1.8, 3.6, 5.6, 7.9, 11.0, 14.7, 18.8, 22.9, 27.4, 31.8, 36.4, 41.0, 45.5, 50.0,
+    ],
+];
+
+/// Kazaross XG2 MWC in percent of the trailer 1-away, 2-away, ... against a leader 1-away
+/// after the Crawford game.
+const KAZAROSS_XG2_POST: [f32; 15] = [
+    50.0, 48.8, 32.1, 31.0, 18.8, 17.8, 11.5, 10.8, 7.0, 6.6, 4.3, 4.0, 2.6, 2.4, 1.6,
+];
+
+/// Match winning chances for every score of a match, indexed by how many points each player needs.
+#[derive(Clone, Debug)]
+pub struct MatchEquityTable {
+    /// `pre[i][j]` is the MWC of a player `i + 1`-away against an opponent `j + 1`-away,
+    /// before the Crawford game or in the Crawford game itself.
+    pre: Vec<Vec<f32>>,
+    /// `post[i]` is the MWC of the trailer `i + 1`-away against a leader 1-away after the Crawford game.
+    post: Vec<f32>,
+}
+
+impl Default for MatchEquityTable {
+    fn default() -> Self {
+        Self::kazaross_xg2()
+    }
+}
+
+impl MatchEquityTable {
+    /// The published 15-point Kazaross XG2 table. Load a longer table with `from_file`.
+    pub fn kazaross_xg2() -> Self {
+        Self {
+            pre: KAZAROSS_XG2_PRE
+                .iter()
+                .map(|row| row.iter().map(|v| v / 100.0).collect())
+                .collect(),
+            post: KAZAROSS_XG2_POST.iter().map(|v| v / 100.0).collect(),
+        }
+    }
+
+    /// Generates a table up to `max_length`-away, `gammon_rate` being the fraction of wins that are gammons.
+    /// Post-Crawford the trailer doubles immediately, every other game is played cubeless
+    /// between equal players. Ignoring the cube before the Crawford game makes it a rough
+    /// approximation at most scores, use it only for lengths no table covers.
+    pub fn generate(max_length: usize, gammon_rate: f32) -> Self {
+        let g = gammon_rate;
+
+        // post-Crawford each game is played for two points
+        let mut post = vec![0.5; max_length];
+        let post_at = |post: &Vec<f32>, away: isize| {
+            if away <= 0 {
+                1.0
+            } else {
+                post[away as usize - 1]
+            }
+        };
+        for away in 2..=max_length {
+            let away = away as isize;
+            post[away as usize - 1] =
+                0.5 * ((1.0 - g) * post_at(&post, away - 2) + g * post_at(&post, away - 4));
+        }
+
+        let probs = Probabilities {
+            win_n: 0.5 * (1.0 - g),
+            win_g: 0.5 * g,
+            win_b: 0.0,
+            lose_n: 0.5 * (1.0 - g),
+            lose_g: 0.5 * g,
+            lose_b: 0.0,
+        };
+        let mut table = Self {
+            pre: vec![vec![0.5; max_length]; max_length],
+            post,
+        };
+        // every game ends with a lower sum of away scores, so those are filled in first
+        for sum in 2..=2 * max_length {
+            for away in 1..=max_length.min(sum - 1) {
+                let opp_away = sum - away;
+                if opp_away > max_length {
+                    continue;
+                }
+                let state = MatchState::new(
+                    max_length as u32,
+                    (max_length - away) as u32,
+                    (max_length - opp_away) as u32,
+                    away == 1 || opp_away == 1,
+                );
+                table.pre[away - 1][opp_away - 1] = table
+                    .mwc(&probs, &state, 1)
+                    .expect("Smaller scores are covered");
+            }
+        }
+        table
+    }
+
+    /// Loads a table from a plain-text file.
+    ///
+    /// Every non-empty line not starting with `#` is a row of whitespace separated values,
+    /// either as fractions or as percentages. The rows form the square pre-Crawford table,
+    /// row `i` and column `j` being the MWC of a player `i`-away against an opponent `j`-away.
+    /// A line containing `post-crawford` is followed by a single row with the MWC of the
+    /// trailer 1-away, 2-away, ... against a leader 1-away after the Crawford game.
+    pub fn from_file(file_path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(file_path)?;
+        let reader = BufReader::new(file);
+
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+        let mut pre = Vec::new();
+        let mut post = None;
+        let mut in_post = false;
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.to_lowercase().contains("post-crawford") {
+                in_post = true;
+                continue;
+            }
+            let row = line
+                .split_whitespace()
+                .map(|value| {
+                    value
+                        .trim_end_matches('%')
+                        .parse::<f32>()
+                        .map_err(|err| invalid(format!("line {}: {}", number + 1, err)))
+                })
+                .collect::<io::Result<Vec<f32>>>()?;
+            if in_post {
+                post = Some(row);
+                in_post = false;
+            } else {
+                pre.push(row);
+            }
+        }
+
+        let post = post.ok_or_else(|| invalid("missing post-crawford row".to_string()))?;
+        if pre.is_empty() || pre.iter().any(|row| row.len() != pre.len()) {
+            return Err(invalid("pre-crawford table is not square".to_string()));
+        }
+        if post.len() != pre.len() {
+            return Err(invalid(format!(
+                "post-crawford row has {} values, expected {}",
+                post.len(),
+                pre.len()
+            )));
+        }
+
+        let percent = pre.iter().flatten().chain(post.iter()).any(|v| *v > 1.0);
+        let scale = |v: f32| if percent { v / 100.0 } else { v };
+        Ok(Self {
+            pre: pre
+                .into_iter()
+                .map(|row| row.into_iter().map(scale).collect())
+                .collect(),
+            post: post.into_iter().map(scale).collect(),
+        })
+    }
+
+    /// Longest match length covered by this table.
+    pub fn max_length(&self) -> u32 {
+        self.pre.len() as u32
+    }
+
+    /// MWC of a player `away` points away against an opponent `opp_away` points away.
+    /// `crawford` tells whether the next game is the Crawford game.
+    /// `None` if the score isn't covered by the table.
+    pub fn get(&self, away: i32, opp_away: i32, crawford: bool) -> Option<f32> {
+        let max_length = self.max_length() as i32;
+        if away > max_length || opp_away > max_length {
+            None
+        } else if away <= 0 {
+            Some(1.0)
+        } else if opp_away <= 0 {
+            Some(0.0)
+        } else if !crawford && away == 1 && opp_away > 1 {
+            Some(1.0 - self.post[opp_away as usize - 1])
+        } else if !crawford && opp_away == 1 && away > 1 {
+            Some(self.post[away as usize - 1])
+        } else {
+            Some(self.pre[away as usize - 1][opp_away as usize - 1])
+        }
+    }
+
+    /// MWC of the player on roll after the current game ends with `points` (positive or negative).
+    pub fn after_game(&self, state: &MatchState, points: i32) -> Option<f32> {
+        let away = state.away() as i32 - points.max(0);
+        let opp_away = state.opp_away() as i32 + points.min(0);
+        let crawford = state.is_pre_crawford() && (away == 1 || opp_away == 1);
+        self.get(away, opp_away, crawford)
+    }
+
+    /// Cubeless MWC of the player on roll for a game played with the cube on `cube`.
+    pub fn mwc(&self, probs: &Probabilities, state: &MatchState, cube: u32) -> Option<f32> {
+        let cube = cube as i32;
+        Some(
+            probs.win_n * self.after_game(state, cube)?
+                + probs.win_g * self.after_game(state, 2 * cube)?
+                + probs.win_b * self.after_game(state, 3 * cube)?
+                + probs.lose_n * self.after_game(state, -cube)?
+                + probs.lose_g * self.after_game(state, -2 * cube)?
+                + probs.lose_b * self.after_game(state, -3 * cube)?,
+        )
+    }
+
+    /// Converts a MWC into equivalent money game equity (EMG) for the cube on `cube`.
+    /// `None` if winning and losing a single game lead to the same MWC.
+    pub fn emg(&self, mwc: f32, state: &MatchState, cube: u32) -> Option<f32> {
+        let win = self.after_game(state, cube as i32)?;
+        let lose = self.after_game(state, -(cube as i32))?;
+        if (win - lose).abs() < f32::EPSILON {
+            return None;
+        }
+        Some(2.0 * (mwc - lose) / (win - lose) - 1.0)
+    }
+}
+
+#[cfg(test)]
+mod matchplay_tests {
+    use crate::matchplay::{MatchEquityTable, MatchState};
+    use crate::probabilities::Probabilities;
+
+    #[test]
+    fn symmetric() {
+        let met = MatchEquityTable::default();
+        for away in 1..=15 {
+            for opp_away in 1..=15 {
+                let sum =
+                    met.get(away, opp_away, true).unwrap() + met.get(opp_away, away, true).unwrap();
+                assert!((sum - 1.0).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn kazaross_xg2() {
+        let met = MatchEquityTable::default();
+        assert_eq!(met.max_length(), 15);
+        assert_eq!(met.get(2, 2, false), Some(0.5));
+        assert!((met.get(3, 1, true).unwrap() - 0.248).abs() < 1e-6);
+        assert!((met.get(1, 2, true).unwrap() - 0.68).abs() < 1e-6);
+        assert!((met.get(2, 1, false).unwrap() - 0.488).abs() < 1e-6);
+        assert!((met.get(5, 7, false).unwrap() - 0.629).abs() < 1e-6);
+    }
+
+    #[test]
+    fn generated_symmetric() {
+        let met = MatchEquityTable::generate(7, 0.25);
+        for away in 1..=7 {
+            for opp_away in 1..=7 {
+                let sum =
+                    met.get(away, opp_away, true).unwrap() + met.get(opp_away, away, true).unwrap();
+                assert!((sum - 1.0).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn leader_is_favourite() {
+        let met = MatchEquityTable::default();
+        let get = |away, opp_away| met.get(away, opp_away, false).unwrap();
+        assert!((get(5, 5) - 0.5).abs() < 1e-5);
+        assert!(get(2, 5) > get(3, 5));
+        assert!(get(3, 5) > 0.5);
+        assert_eq!(met.get(16, 5, false), None);
+    }
+
+    #[test]
+    fn sure_win_emg() {
+        let met = MatchEquityTable::default();
+        let state = MatchState::new(7, 2, 3, false);
+        let probs = Probabilities::new(&[1, 0, 0, 0, 0, 0]);
+        let mwc = met.mwc(&probs, &state, 1).unwrap();
+        assert!((met.emg(mwc, &state, 1).unwrap() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn from_file() {
+        let path = std::env::temp_dir().join("rassay-met.txt");
+        std::fs::write(
+            &path,
+            "# 3-point table in percentages\n\
+             50 68 75\n\
+             32 50 60\n\
+             25 40 50\n\
+             post-crawford\n\
+             50 48.5 32\n",
+        )
+        .unwrap();
+        let met = MatchEquityTable::from_file(&path).unwrap();
+        assert_eq!(met.max_length(), 3);
+        assert!((met.get(1, 2, true).unwrap() - 0.68).abs() < 1e-6);
+        assert!((met.get(3, 1, false).unwrap() - 0.32).abs() < 1e-6);
+        assert!((met.get(1, 2, false).unwrap() - 0.515).abs() < 1e-6);
+
+        std::fs::write(&path, "50 68\n32 50\n").unwrap();
+        assert!(MatchEquityTable::from_file(&path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -3,6 +3,7 @@ use std::path::PathBuf;
 use crate::{
     evaluator::{Evaluator, PartialEvaluator},
     inputs::{self, Inputs},
+    probabilities::Probabilities,
 };
 use bincode::de;
 use bkgm::{dice::ALL_21, position, GameResult, State};
@@ -211,6 +212,7 @@ impl<B: AutodiffBackend> TDModel<B> {
 // }
 
 impl<G: State, B: Backend> PartialEvaluator<G> for TDModel<B> {
+    /// Probability of the player on roll winning, the value the network is trained on.
     fn try_eval(&self, pos: &G) -> f32 {
         let device = B::Device::default();
 
@@ -233,6 +235,27 @@ impl<G: State, B: Backend> PartialEvaluator<G> for TDModel<B> {
         }
     }
 
+    /// Evaluates all positions with a single forward pass.
+    fn try_eval_batch(&self, positions: &[G]) -> Vec<f32> {
+        if positions.is_empty() {
+            return Vec::new();
+        }
+        let inputs: Vec<G> = positions
+            .iter()
+            .map(|pos| if pos.turn() { *pos } else { pos.flip() })
+            .collect();
+        let inputs = self.input_tensor(&B::Device::default(), &inputs);
+        let output = self.forward(inputs);
+
+        let values: TensorData = output.into_data();
+        let values: &[f32] = values.as_slice().unwrap();
+        positions
+            .iter()
+            .zip(values)
+            .map(|(pos, value)| if pos.turn() { *value } else { 1.0 - value })
+            .collect()
+    }
+
     fn best_position(&self, position: &G, dice: &bkgm::Dice) -> G {
         let positions = position.possible_positions(dice);
 
@@ -273,6 +296,33 @@ impl<G: State, B: Backend> PartialEvaluator<G> for TDModel<B> {
     }
 }
 
+/// The model only predicts wins, so gammons are left out.
+impl<G: State, B: Backend> Evaluator<G> for TDModel<B> {
+    fn eval(&self, pos: &G) -> Probabilities {
+        Self::win_probabilities(self.try_eval(pos))
+    }
+
+    fn eval_batch(&self, positions: &[G]) -> Vec<Probabilities> {
+        self.try_eval_batch(positions)
+            .into_iter()
+            .map(Self::win_probabilities)
+            .collect()
+    }
+}
+
+impl<B: Backend> TDModel<B> {
+    fn win_probabilities(win: f32) -> Probabilities {
+        Probabilities {
+            win_n: win,
+            win_g: 0.0,
+            win_b: 0.0,
+            lose_n: 1.0 - win,
+            lose_g: 0.0,
+            lose_b: 0.0,
+        }
+    }
+}
+
 pub(crate) const NUM_INPUTS: usize = 202;
 
 pub fn position_to_vec<G: State>(pos: &G) -> Vec<f32> {
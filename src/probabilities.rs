@@ -1,4 +1,5 @@
 use std::fmt;
+use std::ops::{Add, Mul, Sub};
 
 use bkgm::GameResult;
 
@@ -265,6 +266,51 @@ impl From<&[f32; 5]> for Probabilities {
     }
 }
 
+impl Add for Probabilities {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Probabilities {
+            win_n: self.win_n + rhs.win_n,
+            win_g: self.win_g + rhs.win_g,
+            win_b: self.win_b + rhs.win_b,
+            lose_n: self.lose_n + rhs.lose_n,
+            lose_g: self.lose_g + rhs.lose_g,
+            lose_b: self.lose_b + rhs.lose_b,
+        }
+    }
+}
+
+impl Sub for Probabilities {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Probabilities {
+            win_n: self.win_n - rhs.win_n,
+            win_g: self.win_g - rhs.win_g,
+            win_b: self.win_b - rhs.win_b,
+            lose_n: self.lose_n - rhs.lose_n,
+            lose_g: self.lose_g - rhs.lose_g,
+            lose_b: self.lose_b - rhs.lose_b,
+        }
+    }
+}
+
+impl Mul<f32> for Probabilities {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self {
+        Probabilities {
+            win_n: self.win_n * rhs,
+            win_g: self.win_g * rhs,
+            win_b: self.win_b * rhs,
+            lose_n: self.lose_n * rhs,
+            lose_g: self.lose_g * rhs,
+            lose_b: self.lose_b * rhs,
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct ResultCounter {
     results: [u32; 6],
@@ -306,9 +352,98 @@ impl ResultCounter {
     }
 }
 
+/// Like `ResultCounter`, but for fractional results, e.g. from variance reduced rollouts.
+/// Individual results don't have to be valid probabilities, only their mean should be.
+/// Also keeps the sums of squares, so that standard errors can be calculated.
+#[derive(Default, Clone, Copy)]
+pub struct ProbabilitiesSum {
+    sums: [f64; 6],
+    squares: [f64; 6],
+    equity: f64,
+    equity_squares: f64,
+    count: u32,
+}
+
+impl ProbabilitiesSum {
+    pub fn add(&mut self, probs: &Probabilities) {
+        for (i, value) in probs.to_slice().into_iter().enumerate() {
+            self.sums[i] += value as f64;
+            self.squares[i] += (value as f64).powi(2);
+        }
+        let equity = probs.equity() as f64;
+        self.equity += equity;
+        self.equity_squares += equity.powi(2);
+        self.count += 1;
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn combine(self, other: &ProbabilitiesSum) -> Self {
+        let mut sums = self.sums;
+        let mut squares = self.squares;
+        for i in 0..6 {
+            sums[i] += other.sums[i];
+            squares[i] += other.squares[i];
+        }
+        Self {
+            sums,
+            squares,
+            equity: self.equity + other.equity,
+            equity_squares: self.equity_squares + other.equity_squares,
+            count: self.count + other.count,
+        }
+    }
+
+    /// Mean of all added results.
+    pub fn probabilities(&self) -> Probabilities {
+        let mean = self.sums.map(|sum| (sum / self.count as f64) as f32);
+        Probabilities {
+            win_n: mean[0],
+            win_g: mean[1],
+            win_b: mean[2],
+            lose_n: mean[3],
+            lose_g: mean[4],
+            lose_b: mean[5],
+        }
+    }
+
+    /// Standard error of the mean of every outcome.
+    pub fn std_errors(&self) -> Probabilities {
+        let errors: Vec<f32> = (0..6)
+            .map(|i| self.std_error(self.sums[i], self.squares[i]))
+            .collect();
+        Probabilities {
+            win_n: errors[0],
+            win_g: errors[1],
+            win_b: errors[2],
+            lose_n: errors[3],
+            lose_g: errors[4],
+            lose_b: errors[5],
+        }
+    }
+
+    /// Standard error of the mean equity.
+    pub fn equity_std_error(&self) -> f32 {
+        self.std_error(self.equity, self.equity_squares)
+    }
+
+    /// Infinite as long as there are fewer than two results.
+    fn std_error(&self, sum: f64, squares: f64) -> f32 {
+        if self.count < 2 {
+            return f32::INFINITY;
+        }
+        let n = self.count as f64;
+        let variance = ((squares - sum * sum / n) / (n - 1.0)).max(0.0);
+        (variance / n).sqrt() as f32
+    }
+}
+
 #[cfg(test)]
 mod probabilities_tests {
-    use crate::probabilities::Probabilities;
+    use crate::probabilities::{Probabilities, ProbabilitiesSum};
+    use bkgm::GameResult;
 
     #[test]
     fn new() {
@@ -427,4 +562,15 @@ mod probabilities_tests {
         let gv = probabilities.to_gnu();
         assert_eq!(probabilities, Probabilities::from(&gv));
     }
+
+    #[test]
+    fn probabilities_sum_std_error() {
+        let mut sum = ProbabilitiesSum::default();
+        assert_eq!(sum.equity_std_error(), f32::INFINITY);
+        sum.add(&Probabilities::from_result(&GameResult::WinNormal));
+        sum.add(&Probabilities::from_result(&GameResult::LoseNormal));
+        assert_eq!(sum.probabilities().equity(), 0.0);
+        assert_eq!(sum.equity_std_error(), 1.0);
+        assert_eq!(sum.std_errors().win_n, 0.5);
+    }
 }
@@ -14,7 +14,10 @@ use burn::{
 
 use crate::{
     duel,
-    evaluator::{HyperEvaluator, PartialEvaluator, PubEval, RandomEvaluator},
+    evaluator::{
+        HyperEvaluator, PartialEvaluator, PhaseEvaluator, PositionClass, PubEval, RandomEvaluator,
+        SoftmaxEvaluator, TemperatureSchedule,
+    },
     model::TDModel,
 };
 
@@ -22,6 +25,7 @@ pub struct TDConfig {
     learning_rate: f64,
     td_decay: f64,
     exploration: f64,
+    temperature: Option<TemperatureSchedule>,
 }
 
 impl TDConfig {
@@ -30,8 +34,16 @@ impl TDConfig {
             learning_rate,
             td_decay,
             exploration,
+            temperature: None,
         }
     }
+
+    /// Picks non-exploring moves with a softmax policy instead of the best move,
+    /// the schedule being stepped by episode.
+    pub fn with_temperature(mut self, schedule: TemperatureSchedule) -> Self {
+        self.temperature = Some(schedule);
+        self
+    }
 }
 
 pub struct TDTrainer<B: AutodiffBackend> {
@@ -69,7 +81,27 @@ impl<B: AutodiffBackend> TDTrainer<B> {
         }
     }
 
-    fn train_game<G: State>(&mut self, state: &G, model: TDModel<B>) -> TDModel<B> {
+    /// Random move with probability `exploration`, otherwise the best move or a softmax pick.
+    fn choose<G: State, E: PartialEvaluator<G>>(
+        &self,
+        evaluator: E,
+        state: &G,
+        dice: &bkgm::Dice,
+        episode: usize,
+    ) -> G {
+        if fastrand::f64() < self.config.exploration {
+            let positions = state.possible_positions(dice);
+            positions[fastrand::usize(0..positions.len())]
+        } else if let Some(schedule) = self.config.temperature {
+            SoftmaxEvaluator::new(evaluator, schedule)
+                .with_step(episode)
+                .best_position(state, dice)
+        } else {
+            evaluator.best_position(state, dice)
+        }
+    }
+
+    fn train_game<G: State>(&mut self, state: &G, model: TDModel<B>, episode: usize) -> TDModel<B> {
         let mut optim = self.optim.init();
         let mut model = model;
 
@@ -83,14 +115,7 @@ impl<B: AutodiffBackend> TDTrainer<B> {
             let (cur_value, grads) = self.get_grads_value(&state, &model);
             // let cur_value = self.get_value(&state, &model);
             // let grads = GradientsParams::from_grads(cur_value.backward(), &model);
-            state = model.best_position(&state, &dice);
-
-            if fastrand::f64() < self.config.exploration {
-                let positions = state.possible_positions(&dice);
-                state = positions[fastrand::usize(0..positions.len())];
-            } else {
-                state = model.best_position(&state, &dice);
-            }
+            state = self.choose(&model, &state, &dice, episode);
 
             dice = dicegen.roll();
             let next_value = self.get_value(&state, &model);
@@ -112,7 +137,7 @@ impl<B: AutodiffBackend> TDTrainer<B> {
         let mut model = model;
         let mut prev_model = model.clone();
         for ep in 1..=num_episodes {
-            model = self.train_game(state, model.clone());
+            model = self.train_game(state, model.clone(), ep);
             if ep % 100 == 0 {
                 print!("\rEpisode: {}", ep);
                 stdout().flush().unwrap();
@@ -144,4 +169,96 @@ impl<B: AutodiffBackend> TDTrainer<B> {
         }
         model
     }
+
+    /// Like `train_game`, but every position is valued and trained by the model of its class.
+    fn train_game_phased<G: State>(
+        &mut self,
+        state: &G,
+        models: Vec<TDModel<B>>,
+        episode: usize,
+    ) -> Vec<TDModel<B>> {
+        let mut optims: Vec<_> = PositionClass::ALL
+            .iter()
+            .map(|_| self.optim.init())
+            .collect();
+        let mut models = models.into_iter();
+        // the models are trained in place, so the evaluator picks moves with the latest weights
+        let mut evaluator = PhaseEvaluator::new(
+            models.next().unwrap(),
+            models.next().unwrap(),
+            models.next().unwrap(),
+        );
+
+        let mut dicegen = FastrandDice::new();
+        let mut dice = dicegen.roll_mixed();
+        let mut state = state.clone();
+
+        while state.game_state() == Ongoing {
+            let class = evaluator.class(&state);
+            let (cur_value, grads) = self.get_grads_value(&state, evaluator.evaluator(class));
+
+            state = self.choose(&evaluator, &state, &dice, episode);
+
+            dice = dicegen.roll();
+            let next_value = self.get_value(&state, evaluator.evaluator(evaluator.class(&state)));
+            let td_error = next_value - cur_value;
+            let model = evaluator.evaluator(class).clone();
+            *evaluator.evaluator_mut(class) = optims[class.index()].step(
+                -self.config.learning_rate * td_error as f64,
+                model,
+                grads,
+            );
+        }
+
+        evaluator.into_evaluators()
+    }
+
+    /// Trains one model per `PositionClass`, `models` being ordered like `PositionClass::ALL`.
+    pub fn train_phased<G: State>(
+        &mut self,
+        state: &G,
+        models: Vec<TDModel<B>>,
+        num_episodes: usize,
+        dir: String,
+    ) -> Vec<TDModel<B>> {
+        assert_eq!(
+            models.len(),
+            PositionClass::ALL.len(),
+            "one model per position class"
+        );
+        let mut models = models;
+        for ep in 1..=num_episodes {
+            models = self.train_game_phased(state, models, ep);
+            if ep % 100 == 0 {
+                print!("\rEpisode: {}", ep);
+                stdout().flush().unwrap();
+            }
+
+            if ep % 2_000 == 0 {
+                println!("Saving models");
+                for (class, model) in PositionClass::ALL.iter().zip(&models) {
+                    let name = format!("{:?}", class).to_lowercase();
+                    model
+                        .clone()
+                        .save_file(
+                            format!("{}/{}-games-{}", dir, name, ep),
+                            &NoStdTrainingRecorder::new(),
+                        )
+                        .expect("Failed to save model");
+                }
+            }
+            if ep % 10_000 == 0 {
+                let evaluator =
+                    PhaseEvaluator::new(models[0].clone(), models[1].clone(), models[2].clone());
+                let probs = duel::duel(state, evaluator, PubEval::new(), 1000);
+                println!(
+                    "Equity: {:.3} ({:.1}%). {:?}",
+                    probs.equity(),
+                    probs.win_prob() * 100.0,
+                    probs,
+                );
+            }
+        }
+        models
+    }
 }